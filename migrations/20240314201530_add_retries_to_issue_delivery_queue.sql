@@ -0,0 +1,3 @@
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN n_attempts SMALLINT NOT NULL DEFAULT 0,
+    ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,61 @@
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    PRIMARY KEY(list_id)
+);
+
+-- Everybody who subscribed so far signed up for our original newsletter,
+-- which remains the list used when none is specified.
+INSERT INTO lists (list_id, slug, name)
+VALUES ('5b1f0a8e-6f4c-4c1e-9a57-3f0d2c1e7b42', 'newsletter', 'Newsletter');
+
+CREATE TABLE list_subscriptions (
+    list_id uuid NOT NULL
+        REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY(list_id, subscriber_id)
+);
+
+INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+SELECT '5b1f0a8e-6f4c-4c1e-9a57-3f0d2c1e7b42', id, status, subscribed_at
+FROM subscriptions;
+
+-- From now on `subscriptions.status` only tracks whether the email address
+-- has been confirmed: leaving a list is recorded in `list_subscriptions`.
+-- Confirming burns the tokens of a subscriber, so those who unsubscribed with
+-- a token outstanding never confirmed their current address (e.g. they
+-- unsubscribed after changing it).
+UPDATE subscriptions s
+SET status = CASE
+    WHEN EXISTS (SELECT 1 FROM subscription_tokens t WHERE t.subscriber_id = s.id)
+        THEN 'pending_confirmation'
+    ELSE 'confirmed'
+END
+WHERE status = 'unsubscribed';
+
+-- Confirmation tokens without a list confirm a new email address
+ALTER TABLE subscription_tokens
+    ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE subscription_tokens t
+SET list_id = '5b1f0a8e-6f4c-4c1e-9a57-3f0d2c1e7b42'
+WHERE EXISTS (
+    SELECT 1
+    FROM list_subscriptions l
+    WHERE l.subscriber_id = t.subscriber_id AND l.status = 'pending_confirmation'
+);
+
+CREATE TABLE newsletter_issue_lists (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    list_id uuid NOT NULL
+        REFERENCES lists (list_id),
+    PRIMARY KEY(newsletter_issue_id, list_id)
+);
+
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT newsletter_issue_id, '5b1f0a8e-6f4c-4c1e-9a57-3f0d2c1e7b42'
+FROM newsletter_issues;
@@ -0,0 +1,10 @@
+-- Scheduled issues are only published once the scheduler releases them
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at DROP NOT NULL;
+
+CREATE TABLE scheduled_issues (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    send_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id)
+);
@@ -0,0 +1,3 @@
+-- Issues published before authors were tracked have none
+ALTER TABLE newsletter_issues
+    ADD COLUMN author_user_id uuid NULL REFERENCES users (user_id);
@@ -0,0 +1,11 @@
+-- One row per email the provider accepted, to match its webhook events and
+-- support questions with what we sent
+CREATE TABLE issue_deliveries (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    message_id TEXT NULL,
+    submitted_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
+CREATE INDEX issue_deliveries_message_id_idx ON issue_deliveries (message_id);
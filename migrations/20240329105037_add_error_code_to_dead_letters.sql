@@ -0,0 +1 @@
+ALTER TABLE issue_delivery_dead_letters ADD COLUMN error_code BIGINT NULL;
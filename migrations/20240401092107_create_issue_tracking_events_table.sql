@@ -0,0 +1,13 @@
+-- Opens and clicks of tracked issues, one row per request
+CREATE TABLE issue_tracking_events (
+    event_id uuid PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    url TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX issue_tracking_events_issue_idx
+    ON issue_tracking_events (newsletter_issue_id, kind);
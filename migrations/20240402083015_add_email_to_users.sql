@@ -0,0 +1,2 @@
+-- Where to tell users about changes to their account, if they gave one
+ALTER TABLE users ADD COLUMN email TEXT NULL;
@@ -0,0 +1,2 @@
+-- Subscribers who joined before they could choose read English
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
@@ -0,0 +1,10 @@
+-- Subscribers whose locale has no translation get the original issue
+CREATE TABLE newsletter_issue_translations (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    locale TEXT NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, locale)
+);
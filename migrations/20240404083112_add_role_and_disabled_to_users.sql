@@ -0,0 +1,13 @@
+-- Users we had so far ran the whole place: they become owners
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner';
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT false;
+-- Deleting a user keeps the issues they wrote and forgets their saved responses
+ALTER TABLE newsletter_issues
+    DROP CONSTRAINT newsletter_issues_author_user_id_fkey,
+    ADD CONSTRAINT newsletter_issues_author_user_id_fkey
+        FOREIGN KEY (author_user_id) REFERENCES users (user_id) ON DELETE SET NULL;
+ALTER TABLE idempotency
+    DROP CONSTRAINT idempotency_user_id_fkey,
+    ADD CONSTRAINT idempotency_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE;
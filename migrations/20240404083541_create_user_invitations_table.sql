@@ -0,0 +1,8 @@
+-- Invited users get an account once they choose a password
+CREATE TABLE user_invitations(
+    invitation_token TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
@@ -6,10 +6,13 @@ use actix_web_lab::middleware::Next;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use uuid::Uuid;
 
+use super::{get_role, Role};
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::http::Method;
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use sqlx::PgPool;
 
 #[derive(Copy, Clone, Debug)]
 pub struct UserId(Uuid);
@@ -45,3 +48,79 @@ pub async fn reject_anonymous_users(
         }
     }
 }
+
+/// Only let users make the requests their role allows, e.g. publishing takes
+/// an editor. Disabled and deleted users are logged out.
+/// Must run after `reject_anonymous_users`.
+pub async fn reject_unauthorised_users(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .ok_or_else(|| e500("The user has not been authenticated"))?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500("The database pool is missing"))?;
+    let Some(role) = get_role(*user_id, &pool).await.map_err(e500)? else {
+        let session = {
+            let (http_request, payload) = req.parts_mut();
+            TypedSession::from_request(http_request, payload).await
+        }?;
+        session.log_out();
+        let response = see_other("/login");
+        let e = anyhow::anyhow!("The user has been disabled or deleted");
+        return Err(InternalError::from_response(e, response).into());
+    };
+    if role < required_role(req.method(), req.path()) {
+        let response = HttpResponse::Forbidden().body("Your role does not allow this.");
+        let e = anyhow::anyhow!("The user is a {}", role.as_str());
+        return Err(InternalError::from_response(e, response).into());
+    }
+    req.extensions_mut().insert(role);
+    next.call(req).await
+}
+
+/// The least role allowed to make a request to the admin area: everybody can
+/// look around and manage their own account, but changes take an editor and
+/// users are managed by owners.
+fn required_role(method: &Method, path: &str) -> Role {
+    if path == "/admin/users" || path.starts_with("/admin/users/") {
+        Role::Owner
+    } else if method == Method::GET
+        || ["/admin/password", "/admin/email", "/admin/logout"].contains(&path)
+    {
+        Role::Viewer
+    } else {
+        Role::Editor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::required_role;
+    use crate::authentication::Role;
+    use actix_web::http::Method;
+
+    #[test]
+    fn changes_take_an_editor_and_users_an_owner() {
+        let cases = [
+            (Method::GET, "/admin/dashboard", Role::Viewer),
+            (Method::POST, "/admin/password", Role::Viewer),
+            (Method::POST, "/admin/email", Role::Viewer),
+            (Method::POST, "/admin/logout", Role::Viewer),
+            (Method::GET, "/admin/drafts", Role::Viewer),
+            (Method::POST, "/admin/drafts", Role::Editor),
+            (Method::POST, "/admin/newsletters", Role::Editor),
+            (Method::GET, "/admin/users", Role::Owner),
+            (Method::POST, "/admin/users/delete", Role::Owner),
+            (Method::GET, "/admin/usersettings", Role::Viewer),
+        ];
+        for (method, path, role) in cases {
+            assert_eq!(required_role(&method, path), role, "{} {}", method, path);
+        }
+    }
+}
@@ -1,5 +1,9 @@
 mod middleware;
 pub mod password;
-pub use middleware::reject_anonymous_users;
+mod role;
+mod users;
+pub use middleware::{reject_anonymous_users, reject_unauthorised_users};
 pub use password::{change_password, validate_credentials, AuthError, Credentials};
-pub use middleware::UserId;
\ No newline at end of file
+pub use middleware::UserId;
+pub use role::{get_role, Role};
+pub use users::{create_user, username_is_taken, INVITATION_TTL_DAYS};
@@ -1,7 +1,9 @@
 use crate::telemetry::spawn_blocking_with_tracing;
+use actix_web::http::header::HeaderMap;
 use anyhow::Context;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use base64::Engine;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 #[derive(thiserror::Error, Debug)]
@@ -17,13 +19,46 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
+/// Extract the credentials of the `Authorization` header, `Basic` scheme.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    // The header value, if present, must be a valid UTF8 string
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+    // Split into two segments, using ':' as delimiter
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+    Ok(Credentials {
+        username,
+        password: Secret::new(password),
+    })
+}
+
+/// Disabled users have no credentials: they cannot log in anymore.
 #[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
 async fn get_stored_credentials(
     username: &str,
     pool: &PgPool,
 ) -> Result<Option<(uuid::Uuid, Secret<String>)>, anyhow::Error> {
     let row = sqlx::query!(
-        r#" SELECT user_id, password_hash FROM users WHERE username = $1 "#,
+        r#" SELECT user_id, password_hash FROM users WHERE username = $1 AND NOT disabled "#,
         username,
     )
     .fetch_optional(pool)
@@ -64,7 +99,7 @@ fn verify_password_hash(
     expected_password_hash: Secret<String>,
     password_candidate: Secret<String>,
 ) -> Result<(), AuthError> {
-    let expected_password_hash = PasswordHash::new(&expected_password_hash.expose_secret())
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
     Argon2::default()
         .verify_password(
@@ -81,9 +116,7 @@ pub async fn change_password(
     password: Secret<String>,
     pool: &PgPool,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(||compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let password_hash = hash_password(password).await?;
     sqlx::query!(
         r#"
         UPDATE users 
@@ -98,6 +131,13 @@ pub async fn change_password(
     .context("Failed to change user's password in the database.")?;
     Ok(())
 }
+/// Hash a password to store it, off the async runtime.
+pub async fn hash_password(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+    spawn_blocking_with_tracing(|| compute_password_hash(password))
+        .await?
+        .context("Failed to hash password")
+}
+
 fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
     let password_hash = Argon2::new(
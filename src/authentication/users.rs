@@ -0,0 +1,62 @@
+use super::password::hash_password;
+use super::Role;
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// How many days invited users have to choose their password.
+pub const INVITATION_TTL_DAYS: i32 = 7;
+
+/// Store a new user who can log in straight away.
+#[tracing::instrument(name = "Create user", skip(transaction, password))]
+pub async fn create_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: Option<&SubscriberEmail>,
+    role: Role,
+    password: Secret<String>,
+) -> Result<Uuid, anyhow::Error> {
+    let user_id = Uuid::new_v4();
+    let password_hash = hash_password(password).await?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, role, password_hash)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        username,
+        email.map(AsRef::as_ref),
+        role.as_str(),
+        password_hash.expose_secret(),
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to insert the new user in the database.")?;
+    Ok(user_id)
+}
+
+/// Whether a user, or someone whose invitation is still valid, already goes
+/// by this name.
+#[tracing::instrument(name = "Check username availability", skip(pool))]
+pub async fn username_is_taken(username: &str, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            EXISTS (SELECT 1 FROM users WHERE username = $1)
+            OR EXISTS (
+                SELECT 1 FROM user_invitations
+                WHERE username = $1 AND created_at > now() - make_interval(days => $2)
+            )
+            AS "taken!"
+        "#,
+        username,
+        INVITATION_TTL_DAYS,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to look up the username.")?;
+    Ok(row.taken)
+}
@@ -1,6 +1,15 @@
 use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailClient, EmailTransport, FileTransport, PostmarkTransport, RateLimiter, SmtpCredentials,
+    SmtpTransport,
+};
+use crate::startup::HmacSecret;
+use crate::subscriber_links::SubscriberLinks;
 use secrecy::{ExposeSecret, Secret};
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_bool_from_anything, deserialize_number_from_string,
+    deserialize_option_number_from_string,
+};
 use sqlx::postgres::PgConnectOptions;
 use sqlx::postgres::PgSslMode;
 use sqlx::ConnectOptions;
@@ -10,26 +19,123 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub issue_delivery: IssueDeliverySettings,
+    pub welcome_email: WelcomeEmailSettings,
     pub redis_uri: Secret<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
-    base_url: String,
     sender_email: String,
-    pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    /// How many calls to the email provider can be in flight at once.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrency: usize,
+    /// The provider's quota: calls beyond it wait for their turn.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_requests_per_second: Option<u32>,
+    pub transport: EmailTransportSettings,
+    pub webhook: EmailWebhookSettings,
+}
+
+/// The Basic auth credentials our email provider sends along with the
+/// events it posts to `/webhooks/email`.
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailWebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
 }
+
+/// Which `EmailTransport` delivers our emails, picked by its `kind`.
+#[derive(serde::Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EmailTransportSettings {
+    Postmark {
+        base_url: String,
+        authorization_token: Secret<String>,
+    },
+    Smtp {
+        host: String,
+        #[serde(deserialize_with = "deserialize_number_from_string")]
+        port: u16,
+        username: Option<String>,
+        password: Option<Secret<String>>,
+        /// Only turn this off for a relay running on the same machine.
+        #[serde(
+            default = "default_starttls",
+            deserialize_with = "deserialize_bool_from_anything"
+        )]
+        starttls: bool,
+    },
+    /// Save emails under `directory`, or print them if there is none.
+    File { directory: Option<String> },
+}
+
+fn default_starttls() -> bool {
+    true
+}
+
 impl EmailClientSettings {
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        let transport: Box<dyn EmailTransport> = match self.transport {
+            EmailTransportSettings::Postmark {
+                base_url,
+                authorization_token,
+            } => {
+                let base_url = reqwest::Url::parse(&base_url).expect("Failed to parse base url");
+                Box::new(PostmarkTransport::new(
+                    base_url,
+                    authorization_token,
+                    timeout,
+                ))
+            }
+            EmailTransportSettings::Smtp {
+                host,
+                port,
+                username,
+                password,
+                starttls,
+            } => {
+                let credentials = username.map(|username| SmtpCredentials {
+                    username,
+                    password: password.unwrap_or_else(|| Secret::new(String::new())),
+                });
+                Box::new(SmtpTransport::new(
+                    host,
+                    port,
+                    credentials,
+                    starttls,
+                    timeout,
+                ))
+            }
+            EmailTransportSettings::File { directory } => {
+                Box::new(FileTransport::new(directory.map(Into::into)))
+            }
+        };
+        let rate_limiter = self.max_requests_per_second.map(RateLimiter::new);
+        EmailClient::new(sender_email, transport, self.max_concurrency, rate_limiter)
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
-    pub fn base_url(&self) -> Result<reqwest::Url, String> {
-        reqwest::Url::parse(self.base_url.as_str()).map_err(|e| e.to_string())
-    }
+    /// Send emails to Postmark's API at `base_url`, whatever the transport
+    /// configured so far.
     pub fn set_base_url(&mut self, base_url: String) {
-        self.base_url = base_url
+        let authorization_token = match &self.transport {
+            EmailTransportSettings::Postmark {
+                authorization_token,
+                ..
+            } => authorization_token.clone(),
+            _ => Secret::new(String::new()),
+        };
+        self.transport = EmailTransportSettings::Postmark {
+            base_url,
+            authorization_token,
+        };
     }
 
     pub fn timeout(&self) -> std::time::Duration {
@@ -37,13 +143,55 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct IssueDeliverySettings {
+    /// Deliveries that failed this many times are moved to the dead-letter table.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: i16,
+    /// How many deliveries a worker claims and sends in one go.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_backoff_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_backoff_milliseconds: u64,
+}
+impl IssueDeliverySettings {
+    pub fn base_backoff(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.base_backoff_milliseconds)
+    }
+
+    pub fn max_backoff(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.max_backoff_milliseconds)
+    }
+}
+
+/// The email sent to subscribers once they confirm their address.
+#[derive(serde::Deserialize, Clone)]
+pub struct WelcomeEmailSettings {
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub enabled: bool,
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
     pub base_url: String,
-    pub hmac_secret: Secret<String>
+    pub hmac_secret: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub subscription_token_ttl_hours: u64,
+}
+impl ApplicationSettings {
+    pub fn subscription_token_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.subscription_token_ttl_hours * 60 * 60)
+    }
+
+    pub fn subscriber_links(&self) -> SubscriberLinks {
+        let base_url = reqwest::Url::parse(&self.base_url).expect("Invalid base URL");
+        SubscriberLinks::new(base_url, HmacSecret(self.hmac_secret.clone()))
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -68,7 +216,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
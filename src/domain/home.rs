@@ -1,6 +1,23 @@
-use actix_web::{http::header::ContentType, HttpResponse};
-pub async fn home() -> HttpResponse {
+use crate::i18n::Catalogue;
+use actix_web::http::header::{self, ContentType};
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Written in the visitor's language, as their browser tells us.
+pub async fn home(request: HttpRequest) -> HttpResponse {
+    let accept_language = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let catalogue = Catalogue::negotiate(accept_language);
     HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .insert_header((header::CONTENT_LANGUAGE, catalogue.language))
+        .insert_header((header::VARY, "Accept-Language"))
+        .body(format!(
+            include_str!("home.html"),
+            language = catalogue.language,
+            title = catalogue.home_title,
+            welcome = catalogue.home_welcome
+        ))
 }
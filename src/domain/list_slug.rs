@@ -0,0 +1,55 @@
+/// The identifier of a mailing list in URLs and API payloads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_too_long = s.len() > 64;
+        let has_forbidden_characters = !s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if s.is_empty() || is_too_long || has_forbidden_characters {
+            Err(format!("{} is not a valid list slug.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+/// The list people subscribed to before we ran several newsletters.
+impl Default for ListSlug {
+    fn default() -> Self {
+        Self("newsletter".into())
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ListSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_letters_digits_and_hyphens_are_valid() {
+        assert_ok!(ListSlug::parse("rust-weekly-2".to_string()));
+    }
+    #[test]
+    fn empty_slug_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+    #[test]
+    fn slug_longer_than_64_characters_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+    #[test]
+    fn slug_with_uppercase_letters_or_spaces_is_rejected() {
+        for slug in ["Rust", "rust weekly", "rust/weekly"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+}
@@ -0,0 +1,71 @@
+/// A language, optionally narrowed to a region: `en`, `fr-CA`, `es-419`.
+/// Stored normalised, with a lowercase language and an uppercase region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale(String);
+
+impl Locale {
+    pub fn parse(s: String) -> Result<Locale, String> {
+        let (language, region) = match s.trim().split_once(['-', '_']) {
+            Some((language, region)) => (language, Some(region)),
+            None => (s.trim(), None),
+        };
+        let is_valid_language =
+            (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic());
+        let is_valid_region = region.is_none_or(|region| {
+            (region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()))
+                || (region.len() == 3 && region.chars().all(|c| c.is_ascii_digit()))
+        });
+        if !is_valid_language || !is_valid_region {
+            return Err(format!("{} is not a valid locale.", s));
+        }
+        let mut locale = language.to_ascii_lowercase();
+        if let Some(region) = region {
+            locale.push('-');
+            locale.push_str(&region.to_ascii_uppercase());
+        }
+        Ok(Self(locale))
+    }
+
+    /// The locale without its region.
+    pub fn language(&self) -> &str {
+        self.0.split('-').next().unwrap_or(&self.0)
+    }
+}
+
+/// What subscribers who did not say get, and what we fall back to.
+impl Default for Locale {
+    fn default() -> Self {
+        Self("en".into())
+    }
+}
+
+impl AsRef<str> for Locale {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn languages_and_regions_are_normalised() {
+        assert_ok_eq!(Locale::parse("FR".into()), Locale("fr".into()));
+        assert_ok_eq!(Locale::parse("fr_ca".into()), Locale("fr-CA".into()));
+        assert_ok_eq!(Locale::parse("es-419".into()), Locale("es-419".into()));
+    }
+    #[test]
+    fn the_language_drops_the_region() {
+        assert_eq!(Locale::parse("pt-BR".into()).unwrap().language(), "pt");
+    }
+    #[test]
+    fn malformed_locales_are_rejected() {
+        for locale in [
+            "", "e", "english", "en-", "en-USA", "en-1", "../en", "fr-CA-x",
+        ] {
+            assert_err!(Locale::parse(locale.into()));
+        }
+    }
+}
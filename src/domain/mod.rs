@@ -1,8 +1,14 @@
+mod list_slug;
+mod locale;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_status;
 mod home; 
 pub use home::*;
+pub use list_slug::ListSlug;
+pub use locale::Locale;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscription_status::SubscriptionStatus;
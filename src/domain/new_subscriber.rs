@@ -1,7 +1,10 @@
+use crate::domain::locale::Locale;
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    /// Missing if the subscriber did not say.
+    pub locale: Option<Locale>,
 }
@@ -0,0 +1,203 @@
+use super::{EmailClientError, EmailHeader};
+use crate::domain::SubscriberEmail;
+
+/// An email to a single recipient, put together one field at a time, e.g.
+/// `EmailMessage::new(recipient, "Welcome!").html(html).text(text).tag("welcome")`.
+pub struct EmailMessage {
+    pub(super) recipient: SubscriberEmail,
+    pub(super) subject: String,
+    pub(super) html_content: String,
+    pub(super) text_content: String,
+    pub(super) reply_to: Option<SubscriberEmail>,
+    pub(super) cc: Vec<SubscriberEmail>,
+    pub(super) headers: Vec<EmailHeader>,
+    pub(super) tag: Option<String>,
+    pub(super) attachments: Vec<Attachment>,
+}
+
+impl EmailMessage {
+    pub fn new(recipient: SubscriberEmail, subject: impl Into<String>) -> Self {
+        Self {
+            recipient,
+            subject: subject.into(),
+            html_content: String::new(),
+            text_content: String::new(),
+            reply_to: None,
+            cc: Vec::new(),
+            headers: Vec::new(),
+            tag: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    pub fn html(mut self, content: impl Into<String>) -> Self {
+        self.html_content = content.into();
+        self
+    }
+
+    pub fn text(mut self, content: impl Into<String>) -> Self {
+        self.text_content = content.into();
+        self
+    }
+
+    pub fn reply_to(mut self, address: SubscriberEmail) -> Self {
+        self.reply_to = Some(address);
+        self
+    }
+
+    pub fn cc(mut self, address: SubscriberEmail) -> Self {
+        self.cc.push(address);
+        self
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push(EmailHeader {
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// A label to group emails by in the provider's statistics. Transports
+    /// without such a feature ignore it.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    pub fn recipient(&self) -> &SubscriberEmail {
+        &self.recipient
+    }
+
+    /// Everyone the email goes to.
+    pub(super) fn recipients(&self) -> impl Iterator<Item = &SubscriberEmail> {
+        std::iter::once(&self.recipient).chain(&self.cc)
+    }
+
+    /// Roughly how many bytes the email weighs once sent, attachments being
+    /// counted base64-encoded like providers do.
+    pub fn size(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|h| h.name.len() + h.value.len())
+            .sum();
+        let attachments: usize = self
+            .attachments
+            .iter()
+            .map(|a| a.name.len() + a.content.len().div_ceil(3) * 4)
+            .sum();
+        self.subject.len()
+            + self.html_content.len()
+            + self.text_content.len()
+            + headers
+            + attachments
+    }
+
+    /// Refuse, before any call is made, an email the provider would reject
+    /// for its size or its number of recipients.
+    pub(super) fn check(&self, limits: &EmailLimits) -> Result<(), EmailClientError> {
+        let size = self.size();
+        if size > limits.max_size {
+            return Err(EmailClientError::TooLarge(format!(
+                "The email weighs {} bytes, the limit is {}",
+                size, limits.max_size
+            )));
+        }
+        let n_recipients = self.recipients().count();
+        if n_recipients > limits.max_recipients {
+            return Err(EmailClientError::TooLarge(format!(
+                "The email has {} recipients, the limit is {}",
+                n_recipients, limits.max_recipients
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A file sent along with an email. Inline attachments are not listed as
+/// files by mail clients: the HTML body shows them with `cid:` URLs.
+pub struct Attachment {
+    pub(super) name: String,
+    pub(super) content_type: String,
+    pub(super) content: Vec<u8>,
+    pub(super) content_id: Option<String>,
+}
+
+impl Attachment {
+    pub fn new(name: impl Into<String>, content_type: impl Into<String>, content: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            content_type: content_type.into(),
+            content,
+            content_id: None,
+        }
+    }
+
+    /// An image the HTML body refers to as `<img src="cid:{content_id}">`.
+    pub fn inline(
+        content_id: impl Into<String>,
+        name: impl Into<String>,
+        content_type: impl Into<String>,
+        content: Vec<u8>,
+    ) -> Self {
+        Self {
+            content_id: Some(content_id.into()),
+            ..Self::new(name, content_type, content)
+        }
+    }
+}
+
+/// What a provider accepts in a single email.
+pub struct EmailLimits {
+    /// In bytes, attachments included.
+    pub max_size: usize,
+    /// To and Cc addresses together.
+    pub max_recipients: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attachment, EmailLimits, EmailMessage};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailClientError;
+    use claims::assert_ok;
+
+    fn address(email: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(email.into()).unwrap()
+    }
+
+    const LIMITS: EmailLimits = EmailLimits {
+        max_size: 1000,
+        max_recipients: 2,
+    };
+
+    #[test]
+    fn attachments_count_towards_the_size_once_encoded() {
+        let message = EmailMessage::new(address("recipient@example.com"), "").attachment(
+            Attachment::new("a.bin", "application/octet-stream", vec![0; 750]),
+        );
+        assert_eq!(message.size(), "a.bin".len() + 1000);
+        assert!(matches!(
+            message.check(&LIMITS),
+            Err(EmailClientError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn cc_addresses_count_towards_the_recipients() {
+        let message = EmailMessage::new(address("recipient@example.com"), "Hello")
+            .cc(address("first@example.com"));
+        assert_ok!(message.check(&LIMITS));
+        let message = message.cc(address("second@example.com"));
+        assert!(matches!(
+            message.check(&LIMITS),
+            Err(EmailClientError::TooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,86 @@
+use super::message::{format_message, new_message_id};
+use super::{Email, EmailClientError, EmailTransport, SentEmail};
+use anyhow::Context;
+use chrono::Utc;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+/// Keeps emails on the local machine during development: each one is saved
+/// as an `.eml` file in `directory`, or printed to stdout if there is none.
+pub struct FileTransport {
+    directory: Option<PathBuf>,
+}
+
+impl FileTransport {
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        Self { directory }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<SentEmail, EmailClientError> {
+        let message_id = new_message_id(email);
+        let message = format_message(email, &message_id);
+        let sent = SentEmail {
+            message_id: Some(message_id),
+            submitted_at: Utc::now(),
+        };
+        let Some(directory) = &self.directory else {
+            let mut stdout = tokio::io::stdout();
+            stdout
+                .write_all(message.as_bytes())
+                .await
+                .context("Failed to print the email")?;
+            stdout.flush().await.context("Failed to print the email")?;
+            return Ok(sent);
+        };
+        tokio::fs::create_dir_all(directory)
+            .await
+            .context("Failed to create the email directory")?;
+        let path = directory.join(format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S"),
+            Uuid::new_v4()
+        ));
+        tokio::fs::write(&path, message)
+            .await
+            .context("Failed to save the email")?;
+        tracing::info!(path = %path.display(), "Saved an outgoing email");
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileTransport;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailMessage, EmailTransport};
+    use claims::assert_ok;
+
+    #[tokio::test]
+    async fn emails_are_saved_in_the_directory() {
+        // Arrange
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let transport = FileTransport::new(Some(directory.clone()));
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+        let message = EmailMessage::new(recipient, "Greetings")
+            .html("<p>Hello</p>")
+            .text("Hello");
+        let email = Email {
+            sender: &sender,
+            message: &message,
+        };
+        // Act
+        let outcome = transport.send(&email).await;
+        // Assert
+        assert_ok!(outcome);
+        let files: Vec<_> = std::fs::read_dir(&directory).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let saved = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(saved.contains("To: recipient@example.com\r\n"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+use super::{Attachment, Email};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::Utc;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Render an email as an RFC 5322 message, the format spoken by SMTP relays
+/// and understood by mail clients when saved as an `.eml` file.
+///
+/// The text and HTML contents are the two parts of a `multipart/alternative`
+/// body. Inline images are related to it, and other attachments come after
+/// it in a `multipart/mixed` body. Every part is base64-encoded, which keeps
+/// lines short and means no line of the body can ever start with a dot.
+pub(super) fn format_message(email: &Email, message_id: &str) -> String {
+    let message = email.message;
+    let mut formatted = String::new();
+    write_header(&mut formatted, "From", email.sender.as_ref());
+    write_header(&mut formatted, "To", message.recipient.as_ref());
+    if !message.cc.is_empty() {
+        let cc: Vec<&str> = message.cc.iter().map(AsRef::as_ref).collect();
+        write_header(&mut formatted, "Cc", &cc.join(", "));
+    }
+    if let Some(reply_to) = &message.reply_to {
+        write_header(&mut formatted, "Reply-To", reply_to.as_ref());
+    }
+    write_header(&mut formatted, "Subject", &encode_word(&message.subject));
+    write_header(&mut formatted, "Date", &Utc::now().to_rfc2822());
+    write_header(&mut formatted, "Message-ID", &format!("<{}>", message_id));
+    write_header(&mut formatted, "MIME-Version", "1.0");
+    for header in &message.headers {
+        write_header(&mut formatted, &header.name, &header.value);
+    }
+    let mut body = multipart(
+        "alternative",
+        vec![
+            text_part("text/plain", &message.text_content),
+            text_part("text/html", &message.html_content),
+        ],
+    );
+    let (inline, attached): (Vec<_>, Vec<_>) = message
+        .attachments
+        .iter()
+        .partition(|attachment| attachment.content_id.is_some());
+    if !inline.is_empty() {
+        let parts = std::iter::once(body).chain(inline.into_iter().map(attachment_part));
+        body = multipart("related", parts.collect());
+    }
+    if !attached.is_empty() {
+        let parts = std::iter::once(body).chain(attached.into_iter().map(attachment_part));
+        body = multipart("mixed", parts.collect());
+    }
+    formatted.push_str(&body.headers);
+    formatted.push_str("\r\n");
+    formatted.push_str(&body.body);
+    formatted
+}
+
+/// A globally unique identifier for an email, within the sender's domain.
+pub(super) fn new_message_id(email: &Email) -> String {
+    let sender = email.sender.as_ref();
+    let domain = sender.rsplit('@').next().unwrap_or(sender);
+    format!("{}@{}", Uuid::new_v4(), domain)
+}
+
+/// A MIME entity: its own headers, then its body.
+struct Part {
+    headers: String,
+    body: String,
+}
+
+fn multipart(subtype: &str, parts: Vec<Part>) -> Part {
+    let boundary = format!("boundary-{}", Uuid::new_v4().simple());
+    let mut headers = String::new();
+    write_header(
+        &mut headers,
+        "Content-Type",
+        &format!(r#"multipart/{}; boundary="{}""#, subtype, boundary),
+    );
+    let mut body = String::new();
+    for part in parts {
+        write!(body, "--{}\r\n{}\r\n{}", boundary, part.headers, part.body).unwrap();
+    }
+    write!(body, "--{}--\r\n", boundary).unwrap();
+    Part { headers, body }
+}
+
+fn text_part(content_type: &str, content: &str) -> Part {
+    let mut headers = String::new();
+    write_header(
+        &mut headers,
+        "Content-Type",
+        &format!("{}; charset=utf-8", content_type),
+    );
+    write_header(&mut headers, "Content-Transfer-Encoding", "base64");
+    Part {
+        headers,
+        body: encode_lines(content.as_bytes()),
+    }
+}
+
+fn attachment_part(attachment: &Attachment) -> Part {
+    // Quotes would end the parameter early
+    let name = encode_word(&attachment.name.replace(['"', '\\'], ""));
+    let mut headers = String::new();
+    write_header(
+        &mut headers,
+        "Content-Type",
+        &format!(r#"{}; name="{}""#, attachment.content_type, name),
+    );
+    write_header(&mut headers, "Content-Transfer-Encoding", "base64");
+    let disposition = match &attachment.content_id {
+        Some(content_id) => {
+            write_header(&mut headers, "Content-ID", &format!("<{}>", content_id));
+            "inline"
+        }
+        None => "attachment",
+    };
+    write_header(
+        &mut headers,
+        "Content-Disposition",
+        &format!(r#"{}; filename="{}""#, disposition, name),
+    );
+    Part {
+        headers,
+        body: encode_lines(&attachment.content),
+    }
+}
+
+/// Line breaks are replaced so that a value cannot smuggle in extra headers.
+fn write_header(message: &mut String, name: &str, value: &str) {
+    let value = value.replace(['\r', '\n'], " ");
+    write!(message, "{}: {}\r\n", name, value).unwrap();
+}
+
+/// Headers are ASCII-only: anything else goes through an RFC 2047 encoded word.
+fn encode_word(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_owned()
+    } else {
+        format!("=?utf-8?b?{}?=", STANDARD.encode(value))
+    }
+}
+
+fn encode_lines(content: &[u8]) -> String {
+    let encoded = STANDARD.encode(content);
+    let mut lines = String::with_capacity(encoded.len() + encoded.len() / 38);
+    // Base64 output is ASCII, so splitting on bytes never breaks a character
+    for line in encoded.as_bytes().chunks(76) {
+        lines.push_str(std::str::from_utf8(line).unwrap());
+        lines.push_str("\r\n");
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_message, new_message_id};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Attachment, Email, EmailMessage};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    fn address(email: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(email.into()).unwrap()
+    }
+
+    fn format(message: EmailMessage) -> String {
+        let sender = address("sender@example.com");
+        let email = Email {
+            sender: &sender,
+            message: &message,
+        };
+        format_message(&email, &new_message_id(&email))
+    }
+
+    fn message(subject: &str) -> EmailMessage {
+        EmailMessage::new(address("recipient@example.com"), subject)
+            .html("<p>Hello</p>")
+            .text("Hello")
+    }
+
+    #[test]
+    fn the_message_carries_both_contents() {
+        let message = format(message("Greetings"));
+        assert!(message.contains("From: sender@example.com\r\n"));
+        assert!(message.contains("To: recipient@example.com\r\n"));
+        assert!(message.contains("Subject: Greetings\r\n"));
+        assert!(message.contains("Message-ID: <"));
+        assert!(message.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+        assert!(message.contains(&STANDARD.encode("Hello")));
+        assert!(message.contains(&STANDARD.encode("<p>Hello</p>")));
+    }
+
+    #[test]
+    fn non_ascii_subjects_are_encoded() {
+        let message = format(message("Grüße"));
+        assert!(message.contains(&format!(
+            "Subject: =?utf-8?b?{}?=\r\n",
+            STANDARD.encode("Grüße")
+        )));
+    }
+
+    #[test]
+    fn header_values_cannot_inject_headers() {
+        let message = format(message("Greetings\r\nBcc: victim@example.com").header(
+            "List-Unsubscribe",
+            "<https://example.com>\r\nBcc: victim@example.com",
+        ));
+        assert!(!message.contains("\r\nBcc:"));
+    }
+
+    #[test]
+    fn attachments_and_inline_images_get_their_own_parts() {
+        let message = format(
+            message("Greetings")
+                .cc(address("cc@example.com"))
+                .reply_to(address("editor@example.com"))
+                .attachment(Attachment::new(
+                    "notes.txt",
+                    "text/plain",
+                    b"Notes".to_vec(),
+                ))
+                .attachment(Attachment::inline(
+                    "logo",
+                    "logo.png",
+                    "image/png",
+                    vec![1, 2],
+                )),
+        );
+        assert!(message.contains("Cc: cc@example.com\r\n"));
+        assert!(message.contains("Reply-To: editor@example.com\r\n"));
+        assert!(message.contains("Content-Type: multipart/mixed; boundary="));
+        assert!(message.contains("Content-Type: multipart/related; boundary="));
+        assert!(message.contains("Content-Disposition: attachment; filename=\"notes.txt\"\r\n"));
+        assert!(message.contains(&STANDARD.encode("Notes")));
+        assert!(message.contains("Content-ID: <logo>\r\n"));
+        assert!(message.contains("Content-Disposition: inline; filename=\"logo.png\"\r\n"));
+    }
+}
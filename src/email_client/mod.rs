@@ -0,0 +1,262 @@
+mod email_message;
+mod file;
+mod message;
+mod postmark;
+mod rate_limit;
+mod smtp;
+pub use email_message::{Attachment, EmailLimits, EmailMessage};
+pub use file::FileTransport;
+pub use postmark::PostmarkTransport;
+pub use rate_limit::RateLimiter;
+pub use smtp::{SmtpCredentials, SmtpTransport};
+
+use crate::{domain::SubscriberEmail, routes::error_chain_fmt};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// How many times a call is made before giving up on a provider that keeps
+/// answering that we are over its quota.
+const MAX_RATE_LIMITED_ATTEMPTS: usize = 3;
+
+/// Why an email could not be sent, in enough detail for callers to decide
+/// whether it is worth trying again.
+#[derive(thiserror::Error)]
+pub enum EmailClientError {
+    #[error("Timed out while talking to the email provider")]
+    Timeout,
+    #[error("Failed to reach the email provider")]
+    Connection(#[source] anyhow::Error),
+    /// `status` is the HTTP status code, or the reply code of an SMTP relay.
+    /// `error_code` is Postmark's own code for what went wrong.
+    #[error("The email provider rejected the email: {message}")]
+    Rejected {
+        status: Option<u16>,
+        error_code: Option<i64>,
+        message: String,
+        permanent: bool,
+    },
+    #[error("The email provider asked us to slow down for {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+    #[error("The email provider will not deliver to this recipient: {message}")]
+    InvalidRecipient {
+        error_code: Option<i64>,
+        message: String,
+    },
+    /// Caught before calling the provider, which would reject it anyway.
+    #[error("The email is over the provider's limits: {0}")]
+    TooLarge(String),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EmailClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl EmailClientError {
+    /// Whether sending the same email again is bound to fail the same way.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Rejected { permanent, .. } => *permanent,
+            Self::InvalidRecipient { .. } | Self::TooLarge(_) => true,
+            Self::Timeout
+            | Self::Connection(_)
+            | Self::RateLimited { .. }
+            | Self::Unexpected(_) => false,
+        }
+    }
+
+    /// The provider's own code for what went wrong, if it gave one.
+    pub fn error_code(&self) -> Option<i64> {
+        match self {
+            Self::Rejected { error_code, .. } | Self::InvalidRecipient { error_code, .. } => {
+                *error_code
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What the provider told us about an email it accepted.
+#[derive(Debug)]
+pub struct SentEmail {
+    /// The provider's identifier for the email, which its webhook events
+    /// and support team refer to. Missing if the provider did not say.
+    pub message_id: Option<String>,
+    pub submitted_at: DateTime<Utc>,
+}
+
+/// A custom header to attach to an outgoing email.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// Everything a transport needs to deliver a single email.
+pub struct Email<'a> {
+    pub sender: &'a SubscriberEmail,
+    pub message: &'a EmailMessage,
+}
+
+/// The way emails leave the application: an email provider's API, an SMTP
+/// relay, or the local filesystem during development.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &Email<'_>) -> Result<SentEmail, EmailClientError>;
+
+    /// What the provider accepts in a single email, if it says.
+    fn limits(&self) -> Option<EmailLimits> {
+        None
+    }
+
+    /// The most emails a single `send_batch` call accepts.
+    fn max_batch_size(&self) -> usize {
+        1
+    }
+
+    /// Send several emails, returning one result per email in the same
+    /// order. Transports without a batch API send them one at a time.
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, EmailClientError>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            results.push(self.send(email).await);
+        }
+        results
+    }
+}
+
+/// Sends emails through a transport, with at most `max_concurrency` calls
+/// in flight across all its callers and, optionally, no more calls per second
+/// than the provider's quota.
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+    concurrency: Semaphore,
+    rate_limiter: Option<RateLimiter>,
+}
+impl EmailClient {
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<SentEmail, EmailClientError> {
+        let message = EmailMessage::new(recipient.clone(), subject)
+            .html(html_content)
+            .text(text_content);
+        self.send_message(&message).await
+    }
+
+    pub async fn send_message(
+        &self,
+        message: &EmailMessage,
+    ) -> Result<SentEmail, EmailClientError> {
+        self.send_chunk(std::slice::from_ref(message))
+            .await
+            .pop()
+            .expect("The transport did not report on the email")
+    }
+
+    /// Send a batch of emails, split into as many calls as the transport
+    /// needs. There is one result per email, in the same order.
+    pub async fn send_email_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Vec<Result<SentEmail, EmailClientError>> {
+        let calls = messages
+            .chunks(self.transport.max_batch_size().max(1))
+            .map(|chunk| self.send_chunk(chunk));
+        let results = futures::future::join_all(calls).await;
+        results.into_iter().flatten().collect()
+    }
+
+    /// A single call to the transport for the emails within its limits, made
+    /// again after the delay the provider asked for if it says we are over
+    /// the quota.
+    async fn send_chunk(&self, chunk: &[EmailMessage]) -> Vec<Result<SentEmail, EmailClientError>> {
+        let checks: Vec<_> = match self.transport.limits() {
+            Some(limits) => chunk.iter().map(|message| message.check(&limits)).collect(),
+            None => chunk.iter().map(|_| Ok(())).collect(),
+        };
+        let emails: Vec<Email> = chunk
+            .iter()
+            .zip(&checks)
+            .filter(|(_, check)| check.is_ok())
+            .map(|(message, _)| Email {
+                sender: &self.sender,
+                message,
+            })
+            .collect();
+        let mut results = if emails.is_empty() {
+            Vec::new()
+        } else {
+            self.send_to_transport(&emails).await
+        }
+        .into_iter();
+        checks
+            .into_iter()
+            .map(|check| match check {
+                Ok(()) => results.next().unwrap_or_else(|| {
+                    Err(EmailClientError::Unexpected(anyhow::anyhow!(
+                        "The transport did not report on this email"
+                    )))
+                }),
+                Err(e) => Err(e),
+            })
+            .collect()
+    }
+
+    async fn send_to_transport(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Vec<Result<SentEmail, EmailClientError>> {
+        let _permit = self
+            .concurrency
+            .acquire()
+            .await
+            .expect("The semaphore is never closed");
+        let mut attempt = 1;
+        loop {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire().await;
+            }
+            let results = self.transport.send_batch(emails).await;
+            let retry_after = results.iter().find_map(|result| match result {
+                Err(EmailClientError::RateLimited { retry_after }) => Some(*retry_after),
+                _ => None,
+            });
+            let Some(retry_after) = retry_after else {
+                return results;
+            };
+            if attempt >= MAX_RATE_LIMITED_ATTEMPTS {
+                return results;
+            }
+            tracing::warn!(?retry_after, "The email provider is rate limiting us");
+            match &self.rate_limiter {
+                Some(rate_limiter) => rate_limiter.pause_for(retry_after),
+                None => tokio::time::sleep(retry_after).await,
+            }
+            attempt += 1;
+        }
+    }
+
+    pub fn new(
+        sender: SubscriberEmail,
+        transport: Box<dyn EmailTransport>,
+        max_concurrency: usize,
+        rate_limiter: Option<RateLimiter>,
+    ) -> Self {
+        Self {
+            sender,
+            transport,
+            concurrency: Semaphore::new(max_concurrency.max(1)),
+            rate_limiter,
+        }
+    }
+}
@@ -0,0 +1,722 @@
+use super::{
+    Attachment, Email, EmailClientError, EmailHeader, EmailLimits, EmailTransport, SentEmail,
+};
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Response, StatusCode, Url};
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+/// Postmark accepts at most this many messages per batch request.
+const MAX_BATCH_SIZE: usize = 500;
+/// How long to back off when a 429 response does not say.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+/// Postmark's error code for recipients that bounced or complained before.
+const INACTIVE_RECIPIENT: i64 = 406;
+/// Postmark refuses messages over 10 MB, attachments included.
+const MAX_MESSAGE_SIZE: usize = 10 * 1024 * 1024;
+/// Postmark refuses messages with more To, Cc and Bcc addresses than this.
+const MAX_RECIPIENTS: usize = 50;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<PostmarkAttachment<'a>>,
+}
+
+impl<'a> From<&'a Email<'a>> for SendEmailRequest<'a> {
+    fn from(email: &'a Email<'a>) -> Self {
+        let message = email.message;
+        let cc = (!message.cc.is_empty()).then(|| {
+            message
+                .cc
+                .iter()
+                .map(AsRef::as_ref)
+                .collect::<Vec<&str>>()
+                .join(", ")
+        });
+        Self {
+            from: email.sender.as_ref(),
+            to: message.recipient.as_ref(),
+            cc,
+            reply_to: message.reply_to.as_ref().map(AsRef::as_ref),
+            subject: &message.subject,
+            html_body: &message.html_content,
+            text_body: &message.text_content,
+            headers: &message.headers,
+            tag: message.tag.as_deref(),
+            attachments: message.attachments.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkAttachment<'a> {
+    name: &'a str,
+    /// Base64-encoded.
+    content: String,
+    content_type: &'a str,
+    /// `cid:` followed by the id the HTML body refers to, for inline images.
+    #[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+}
+
+impl<'a> From<&'a Attachment> for PostmarkAttachment<'a> {
+    fn from(attachment: &'a Attachment) -> Self {
+        Self {
+            name: &attachment.name,
+            content: STANDARD.encode(&attachment.content),
+            content_type: &attachment.content_type,
+            content_id: attachment
+                .content_id
+                .as_ref()
+                .map(|id| format!("cid:{}", id)),
+        }
+    }
+}
+
+/// What Postmark says about an email: the outcome of each message of a
+/// batch, or the body of an error response.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkResponse {
+    error_code: i64,
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    submitted_at: Option<DateTime<Utc>>,
+}
+
+impl PostmarkResponse {
+    fn into_sent_email(self) -> SentEmail {
+        SentEmail {
+            message_id: self.message_id,
+            submitted_at: self.submitted_at.unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmailClientError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout
+        } else if e.is_connect() {
+            Self::Connection(e.into())
+        } else {
+            Self::Unexpected(e.into())
+        }
+    }
+}
+
+/// Message-level errors and 4xx responses will not change on a retry,
+/// unlike 5xx responses.
+fn rejection(
+    status: Option<StatusCode>,
+    error_code: Option<i64>,
+    message: String,
+) -> EmailClientError {
+    if error_code == Some(INACTIVE_RECIPIENT) {
+        return EmailClientError::InvalidRecipient {
+            error_code,
+            message,
+        };
+    }
+    EmailClientError::Rejected {
+        status: status.map(|s| s.as_u16()),
+        error_code,
+        permanent: error_code.is_some() || status.is_some_and(|s| s.is_client_error()),
+        message,
+    }
+}
+
+/// Turn error responses into the matching `EmailClientError`. A 429 response
+/// means we went over the quota: `Retry-After`, if any, says for how long.
+async fn check_response(response: Response) -> Result<Response, EmailClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after)
+            .unwrap_or(DEFAULT_RETRY_AFTER);
+        return Err(EmailClientError::RateLimited { retry_after });
+    }
+    let body: Option<PostmarkResponse> = response.json().await.ok();
+    let (error_code, message) = match body {
+        Some(body) => (Some(body.error_code), body.message),
+        None => (None, status.to_string()),
+    };
+    Err(rejection(Some(status), error_code, message))
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// A failed batch request fails every email in it, each with its own copy of
+/// the error.
+fn copy_error(e: &EmailClientError) -> EmailClientError {
+    match e {
+        EmailClientError::Timeout => EmailClientError::Timeout,
+        EmailClientError::Connection(e) => EmailClientError::Connection(anyhow::anyhow!("{:#}", e)),
+        EmailClientError::Rejected {
+            status,
+            error_code,
+            message,
+            permanent,
+        } => EmailClientError::Rejected {
+            status: *status,
+            error_code: *error_code,
+            message: message.clone(),
+            permanent: *permanent,
+        },
+        EmailClientError::RateLimited { retry_after } => EmailClientError::RateLimited {
+            retry_after: *retry_after,
+        },
+        EmailClientError::InvalidRecipient {
+            error_code,
+            message,
+        } => EmailClientError::InvalidRecipient {
+            error_code: *error_code,
+            message: message.clone(),
+        },
+        EmailClientError::TooLarge(message) => EmailClientError::TooLarge(message.clone()),
+        EmailClientError::Unexpected(e) => EmailClientError::Unexpected(anyhow::anyhow!("{:#}", e)),
+    }
+}
+
+/// Sends emails through Postmark's JSON API.
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: Url,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: Url,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+
+    async fn post<T: serde::Serialize + ?Sized>(
+        &self,
+        endpoint: &str,
+        body: &T,
+    ) -> Result<Response, EmailClientError> {
+        let url = Url::join(&self.base_url, endpoint).context("Invalid Postmark URL")?;
+        let response = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(body)
+            .send()
+            .await?;
+        check_response(response).await
+    }
+
+    async fn post_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<PostmarkResponse>, EmailClientError> {
+        let request_body: Vec<SendEmailRequest> = emails.iter().map(Into::into).collect();
+        let items = self
+            .post("email/batch", &request_body)
+            .await?
+            .json()
+            .await?;
+        Ok(items)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<SentEmail, EmailClientError> {
+        let response = self.post("email", &SendEmailRequest::from(email)).await?;
+        // The email has been accepted whatever the body says: failing it now
+        // would only get it sent twice
+        let sent = match response.json::<PostmarkResponse>().await {
+            Ok(response) => response.into_sent_email(),
+            Err(e) => {
+                tracing::warn!(error.message = %e, "Failed to parse Postmark's response");
+                SentEmail {
+                    message_id: None,
+                    submitted_at: Utc::now(),
+                }
+            }
+        };
+        Ok(sent)
+    }
+
+    fn limits(&self) -> Option<EmailLimits> {
+        Some(EmailLimits {
+            max_size: MAX_MESSAGE_SIZE,
+            max_recipients: MAX_RECIPIENTS,
+        })
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    /// Postmark answers a batch request with one outcome per message, so a
+    /// rejected recipient does not fail the rest of the batch.
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, EmailClientError>> {
+        // The single email endpoint reports on its only email with the
+        // status code, there is nothing to gain from the batch envelope
+        if let [email] = emails {
+            return vec![self.send(email).await];
+        }
+        let items = match self.post_batch(emails).await {
+            Ok(items) => items,
+            Err(e) => return emails.iter().map(|_| Err(copy_error(&e))).collect(),
+        };
+        let mut items = items.into_iter();
+        emails
+            .iter()
+            .map(|_| match items.next() {
+                Some(item) if item.error_code == 0 => Ok(item.into_sent_email()),
+                Some(item) => Err(rejection(None, Some(item.error_code), item.message)),
+                None => Err(EmailClientError::Unexpected(anyhow::anyhow!(
+                    "Postmark did not report on this email"
+                ))),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_retry_after;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        Attachment, EmailClient, EmailClientError, EmailMessage, PostmarkTransport,
+    };
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use reqwest::Url;
+    use secrecy::Secret;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use wiremock::matchers::any;
+    use wiremock::matchers::{body_partial_json, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+    /// Generate a random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        email_client_with_concurrency(base_url, 1)
+    }
+
+    fn email_client_with_concurrency(base_url: String, max_concurrency: usize) -> EmailClient {
+        let uri = Url::parse(&base_url).expect("Failed to parse URL");
+        let transport = PostmarkTransport::new(
+            uri,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        );
+        EmailClient::new(email(), Box::new(transport), max_concurrency, None)
+    }
+
+    /// Send two emails at once, returning how long after the first call the
+    /// second one reached the server, which takes `delay` to answer each.
+    async fn gap_between_two_calls(max_concurrency: usize, delay: Duration) -> Duration {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_concurrency(mock_server.uri(), max_concurrency);
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+        let arrivals_ = arrivals.clone();
+        Mock::given(any())
+            .respond_with(move |_: &Request| {
+                arrivals_.lock().unwrap().push(Instant::now());
+                ResponseTemplate::new(200).set_delay(delay)
+            })
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let (first, second) = tokio::join!(
+            email_client.send_email(&first, &subject, &content, &content),
+            email_client.send_email(&second, &subject, &content, &content),
+        );
+        assert_ok!(first);
+        assert_ok!(second);
+        let arrivals = arrivals.lock().unwrap();
+        arrivals[1] - arrivals[0]
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let subject: String = Sentence(1..2).fake();
+        let content: String = Paragraph(1..10).fake();
+
+        let _ = email_client
+            .send_email(&email(), &subject, &content, &content)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_message_sends_every_field_of_the_message() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "Cc": "first@example.com, second@example.com",
+                "ReplyTo": "editor@example.com",
+                "Tag": "welcome",
+                "Headers": [{ "Name": "List-Unsubscribe", "Value": "<https://example.com>" }],
+                "Attachments": [
+                    {
+                        "Name": "notes.txt",
+                        "Content": "Tm90ZXM=",
+                        "ContentType": "text/plain"
+                    },
+                    {
+                        "Name": "logo.png",
+                        "Content": "AQI=",
+                        "ContentType": "image/png",
+                        "ContentID": "cid:logo"
+                    }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let address = |email: &str| SubscriberEmail::parse(email.into()).unwrap();
+        let message = EmailMessage::new(email(), subject())
+            .html(r#"<p>Hi!</p><img src="cid:logo">"#)
+            .text("Hi!")
+            .cc(address("first@example.com"))
+            .cc(address("second@example.com"))
+            .reply_to(address("editor@example.com"))
+            .tag("welcome")
+            .header("List-Unsubscribe", "<https://example.com>")
+            .attachment(Attachment::new(
+                "notes.txt",
+                "text/plain",
+                b"Notes".to_vec(),
+            ))
+            .attachment(Attachment::inline(
+                "logo",
+                "logo.png",
+                "image/png",
+                vec![1, 2],
+            ));
+        // Act
+        let outcome = email_client.send_message(&message).await;
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn messages_over_postmark_limits_are_not_sent() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+        let message = EmailMessage::new(email(), subject()).attachment(Attachment::new(
+            "video.mp4",
+            "video/mp4",
+            vec![0; 8 * 1024 * 1024],
+        ));
+        // Act
+        let outcome = email_client.send_message(&message).await;
+        // Assert
+        let Err(e) = outcome else {
+            panic!("Expected the message to be refused");
+        };
+        assert!(matches!(e, EmailClientError::TooLarge(_)));
+        assert!(e.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn send_email_time_out_if_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let email_client = email_client(mock_server.uri());
+        let content = content();
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content, &content)
+            .await;
+        assert!(matches!(outcome, Err(EmailClientError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let email_client = email_client(mock_server.uri());
+        let content = content();
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content, &content)
+            .await;
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let email_client = email_client(mock_server.uri());
+        let subscriber_email = email();
+        let content = content();
+        let outcome = email_client
+            .send_email(&subscriber_email, &subject(), &content, &content)
+            .await;
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn calls_up_to_max_concurrency_are_in_flight_together() {
+        let delay = Duration::from_millis(100);
+        assert!(gap_between_two_calls(2, delay).await < delay);
+    }
+
+    #[tokio::test]
+    async fn calls_beyond_max_concurrency_wait_for_their_turn() {
+        let delay = Duration::from_millis(100);
+        assert!(gap_between_two_calls(1, delay).await >= delay);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_reports_on_each_recipient() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let (subject, content) = (subject(), content());
+        let batch = [email(), email()].map(|recipient| {
+            EmailMessage::new(recipient, &subject)
+                .html(&content)
+                .text(&content)
+        });
+        // Act
+        let outcomes = email_client.send_email_batch(&batch).await;
+        // Assert
+        assert_eq!(outcomes.len(), 2);
+        assert_ok!(&outcomes[0]);
+        assert!(matches!(
+            &outcomes[1],
+            Err(EmailClientError::InvalidRecipient { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fails_every_recipient_if_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let (subject, content) = (subject(), content());
+        let batch = [email(), email()].map(|recipient| {
+            EmailMessage::new(recipient, &subject)
+                .html(&content)
+                .text(&content)
+        });
+        // Act
+        let outcomes = email_client.send_email_batch(&batch).await;
+        // Assert
+        assert_eq!(outcomes.len(), 2);
+        for outcome in outcomes {
+            let Err(e) = outcome else {
+                panic!("Expected every recipient to fail");
+            };
+            assert!(!e.is_permanent());
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_the_status_and_postmark_error_code() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 300,
+                "Message": "Invalid 'From' address"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let content = content();
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content, &content)
+            .await;
+        // Assert
+        let Err(EmailClientError::Rejected {
+            status,
+            error_code,
+            permanent,
+            ..
+        }) = outcome
+        else {
+            panic!("Expected Postmark to reject the email");
+        };
+        assert_eq!(status, Some(422));
+        assert_eq!(error_code, Some(300));
+        assert!(permanent);
+    }
+
+    #[tokio::test]
+    async fn send_email_waits_for_retry_after_when_rate_limited() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(path("/email"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let content = content();
+        let start = std::time::Instant::now();
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content, &content)
+            .await;
+        // Assert
+        assert_ok!(outcome);
+        assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_dates() {
+        assert_eq!(
+            parse_retry_after("120"),
+            Some(std::time::Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(std::time::Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    struct SendEmailBodyMatcher;
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // Check that all the mandatory fields are populated
+                // without inspecting the field values
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                // if parsing fails, the body is not valid JSON
+                false
+            }
+        }
+    }
+}
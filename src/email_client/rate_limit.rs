@@ -0,0 +1,92 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A token bucket holding up to a second's worth of calls to the email
+/// provider: bursts are allowed, but the average rate never exceeds the quota.
+pub struct RateLimiter {
+    per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    /// Set when the provider asked us to back off with `Retry-After`.
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32) -> Self {
+        let per_second = f64::from(per_second.max(1));
+        Self {
+            per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: per_second,
+                refilled_at: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Wait until a call to the provider fits in the quota.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.per_second);
+                bucket.refilled_at = now;
+                match bucket.paused_until {
+                    Some(until) if until > now => until - now,
+                    _ if bucket.tokens >= 1.0 => {
+                        bucket.tokens -= 1.0;
+                        return;
+                    }
+                    _ => Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second),
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Hold every call back for `duration`, including those already waiting.
+    pub fn pause_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.paused_until = Some(bucket.paused_until.map_or(until, |u| u.max(until)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[tokio::test]
+    async fn calls_beyond_the_quota_are_delayed() {
+        // Arrange
+        let limiter = RateLimiter::new(20);
+        let start = Instant::now();
+        // Act
+        for _ in 0..21 {
+            limiter.acquire().await;
+        }
+        // Assert
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn a_pause_holds_back_calls_even_with_tokens_left() {
+        // Arrange
+        let limiter = RateLimiter::new(20);
+        let start = Instant::now();
+        // Act
+        limiter.pause_for(Duration::from_millis(100));
+        limiter.acquire().await;
+        // Assert
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
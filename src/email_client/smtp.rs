@@ -0,0 +1,412 @@
+use super::message::{format_message, new_message_id};
+use super::{Email, EmailClientError, EmailTransport, SentEmail};
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{self, ServerName};
+use tokio_rustls::TlsConnector;
+
+pub struct SmtpCredentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Sends emails to an SMTP relay, one connection per email.
+///
+/// The connection is upgraded with STARTTLS before credentials are sent,
+/// unless `starttls` is turned off to talk to a local relay.
+pub struct SmtpTransport {
+    host: String,
+    port: u16,
+    credentials: Option<SmtpCredentials>,
+    starttls: bool,
+    timeout: Duration,
+    tls: TlsConnector,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: String,
+        port: u16,
+        credentials: Option<SmtpCredentials>,
+        starttls: bool,
+        timeout: Duration,
+    ) -> Self {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Self {
+            host,
+            port,
+            credentials,
+            starttls,
+            timeout,
+            tls: TlsConnector::from(Arc::new(config)),
+        }
+    }
+
+    async fn deliver(&self, email: &Email<'_>) -> Result<SentEmail, EmailClientError> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .context("Failed to connect to the SMTP server")
+            .map_err(EmailClientError::Connection)?;
+        let sender = email.sender.as_ref();
+        let ehlo = format!("EHLO {}", sender.rsplit('@').next().unwrap_or(sender));
+        let mut connection = Connection::new(stream);
+        connection.read_reply(&[220]).await?;
+        let capabilities = connection.command(&ehlo, &[250]).await?;
+        if !self.starttls {
+            return self.transaction(connection, email).await;
+        }
+        if !capabilities
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case("STARTTLS"))
+        {
+            return Err(anyhow::anyhow!("The SMTP server does not support STARTTLS").into());
+        }
+        connection.command("STARTTLS", &[220]).await?;
+        let server_name =
+            ServerName::try_from(self.host.as_str()).context("Invalid SMTP server name")?;
+        let stream = self
+            .tls
+            .connect(server_name, connection.into_inner())
+            .await
+            .context("Failed to upgrade the SMTP connection to TLS")
+            .map_err(EmailClientError::Connection)?;
+        let mut connection = Connection::new(stream);
+        // What the server told us before the upgrade cannot be trusted
+        connection.command(&ehlo, &[250]).await?;
+        self.transaction(connection, email).await
+    }
+
+    async fn transaction<S>(
+        &self,
+        mut connection: Connection<S>,
+        email: &Email<'_>,
+    ) -> Result<SentEmail, EmailClientError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if let Some(credentials) = &self.credentials {
+            let token = STANDARD.encode(format!(
+                "\0{}\0{}",
+                credentials.username,
+                credentials.password.expose_secret()
+            ));
+            connection
+                .command(&format!("AUTH PLAIN {}", token), &[235])
+                .await?;
+        }
+        connection
+            .command(&format!("MAIL FROM:<{}>", email.sender.as_ref()), &[250])
+            .await?;
+        // The relay knows the recipient's mailbox does not exist
+        connection
+            .command(
+                &format!("RCPT TO:<{}>", email.message.recipient.as_ref()),
+                &[250, 251],
+            )
+            .await
+            .map_err(|e| match e {
+                EmailClientError::Rejected {
+                    message,
+                    permanent: true,
+                    ..
+                } => EmailClientError::InvalidRecipient {
+                    error_code: None,
+                    message,
+                },
+                e => e,
+            })?;
+        for cc in &email.message.cc {
+            connection
+                .command(&format!("RCPT TO:<{}>", cc.as_ref()), &[250, 251])
+                .await?;
+        }
+        connection.command("DATA", &[354]).await?;
+        let message_id = new_message_id(email);
+        connection
+            .send_data(&format_message(email, &message_id))
+            .await?;
+        let sent = SentEmail {
+            message_id: Some(message_id),
+            submitted_at: Utc::now(),
+        };
+        // The email has been accepted at this point: a failed goodbye is harmless
+        let _ = connection.command("QUIT", &[221]).await;
+        Ok(sent)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<SentEmail, EmailClientError> {
+        tokio::time::timeout(self.timeout, self.deliver(email))
+            .await
+            .map_err(|_| EmailClientError::Timeout)?
+    }
+}
+
+fn lost_connection(e: std::io::Error) -> EmailClientError {
+    EmailClientError::Connection(
+        anyhow::Error::new(e).context("Lost the connection to the SMTP server"),
+    )
+}
+
+struct Connection<S> {
+    stream: BufReader<S>,
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    async fn command(
+        &mut self,
+        command: &str,
+        expected: &[u16],
+    ) -> Result<Vec<String>, EmailClientError> {
+        self.write(&format!("{}\r\n", command)).await?;
+        self.read_reply(expected).await
+    }
+
+    /// Lines starting with a dot are escaped, and a lone dot ends the data.
+    async fn send_data(&mut self, message: &str) -> Result<(), EmailClientError> {
+        let mut data = String::with_capacity(message.len() + 5);
+        for line in message.split_inclusive("\r\n") {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+        }
+        data.push_str(".\r\n");
+        self.write(&data).await?;
+        self.read_reply(&[250]).await?;
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &str) -> Result<(), EmailClientError> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(data.as_bytes())
+            .await
+            .map_err(lost_connection)?;
+        stream.flush().await.map_err(lost_connection)?;
+        Ok(())
+    }
+
+    /// A reply spans several lines when its code is followed by a dash
+    /// (`250-...`) rather than a space. Negative replies are rejections:
+    /// 4xx replies are transient, 5xx replies will not change on a retry.
+    async fn read_reply(&mut self, expected: &[u16]) -> Result<Vec<String>, EmailClientError> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            let n_read = self
+                .stream
+                .read_line(&mut line)
+                .await
+                .map_err(lost_connection)?;
+            if n_read == 0 {
+                return Err(EmailClientError::Connection(anyhow::anyhow!(
+                    "The SMTP server closed the connection"
+                )));
+            }
+            let line = line.trim_end();
+            let code: u16 = line
+                .get(..3)
+                .and_then(|code| code.parse().ok())
+                .with_context(|| format!("Malformed SMTP reply: {}", line))?;
+            lines.push(line.get(4..).unwrap_or_default().to_owned());
+            if line.as_bytes().get(3) == Some(&b'-') {
+                continue;
+            }
+            if !expected.contains(&code) {
+                return Err(EmailClientError::Rejected {
+                    status: Some(code),
+                    error_code: None,
+                    message: lines.join(" "),
+                    permanent: code >= 500,
+                });
+            }
+            return Ok(lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SmtpCredentials, SmtpTransport};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailClientError, EmailMessage, EmailTransport, SentEmail};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// A scripted SMTP server that accepts a single connection and returns
+    /// every line it received.
+    async fn smtp_server(rcpt_reply: &'static str) -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            let mut received = Vec::new();
+            let mut in_data = false;
+            writer.write_all(b"220 localhost ready\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_owned();
+                let reply = if in_data {
+                    in_data = line != ".";
+                    (!in_data).then_some("250 queued")
+                } else if line.starts_with("EHLO") {
+                    Some("250-localhost\r\n250 AUTH PLAIN")
+                } else if line.starts_with("AUTH") {
+                    Some("235 authenticated")
+                } else if line.starts_with("RCPT") {
+                    Some(rcpt_reply)
+                } else if line == "DATA" {
+                    in_data = true;
+                    Some("354 go ahead")
+                } else if line == "QUIT" {
+                    Some("221 bye")
+                } else {
+                    Some("250 ok")
+                };
+                received.push(line);
+                if let Some(reply) = reply {
+                    writer
+                        .write_all(format!("{}\r\n", reply).as_bytes())
+                        .await
+                        .unwrap();
+                }
+            }
+            received
+        });
+        (port, handle)
+    }
+
+    fn transport(port: u16, starttls: bool) -> SmtpTransport {
+        let credentials = SmtpCredentials {
+            username: "user".into(),
+            password: Secret::new("password".into()),
+        };
+        SmtpTransport::new(
+            "127.0.0.1".into(),
+            port,
+            Some(credentials),
+            starttls,
+            Duration::from_secs(5),
+        )
+    }
+
+    async fn send(transport: &SmtpTransport) -> Result<SentEmail, EmailClientError> {
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+        let message = EmailMessage::new(recipient, "Greetings")
+            .html("<p>Hello</p>")
+            .text(".Hello");
+        let email = Email {
+            sender: &sender,
+            message: &message,
+        };
+        transport.send(&email).await
+    }
+
+    #[tokio::test]
+    async fn the_email_is_submitted_to_the_smtp_server() {
+        // Arrange
+        let (port, server) = smtp_server("250 ok").await;
+        // Act
+        let outcome = send(&transport(port, false)).await;
+        // Assert
+        assert_ok!(outcome);
+        let received = server.await.unwrap();
+        let auth = format!("AUTH PLAIN {}", STANDARD.encode("\0user\0password"));
+        assert_eq!(received[0], "EHLO example.com");
+        assert_eq!(received[1], auth);
+        assert_eq!(received[2], "MAIL FROM:<sender@example.com>");
+        assert_eq!(received[3], "RCPT TO:<recipient@example.com>");
+        assert_eq!(received[4], "DATA");
+        assert!(received.contains(&"Subject: Greetings".to_string()));
+        assert_eq!(received.last().unwrap(), "QUIT");
+    }
+
+    #[tokio::test]
+    async fn rejected_recipients_are_permanent_failures() {
+        // Arrange
+        let (port, _server) = smtp_server("550 no such user").await;
+        // Act
+        let outcome = send(&transport(port, false)).await;
+        // Assert
+        let Err(e) = outcome else {
+            panic!("Expected the delivery to fail");
+        };
+        assert!(matches!(e, EmailClientError::InvalidRecipient { .. }));
+        assert!(e.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn transient_replies_are_not_permanent_failures() {
+        // Arrange
+        let (port, _server) = smtp_server("451 try again later").await;
+        // Act
+        let outcome = send(&transport(port, false)).await;
+        // Assert
+        let Err(EmailClientError::Rejected {
+            status, permanent, ..
+        }) = outcome
+        else {
+            panic!("Expected the relay to reject the email");
+        };
+        assert_eq!(status, Some(451));
+        assert!(!permanent);
+    }
+
+    #[tokio::test]
+    async fn credentials_are_never_sent_without_starttls() {
+        // Arrange
+        let (port, server) = smtp_server("250 ok").await;
+        // Act
+        let outcome = send(&transport(port, true)).await;
+        // Assert
+        assert_err!(outcome);
+        let received = server.await.unwrap();
+        assert!(!received.iter().any(|line| line.starts_with("AUTH")));
+    }
+}
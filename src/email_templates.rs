@@ -0,0 +1,211 @@
+//! The emails we send on our own, as opposed to newsletter issues.
+//!
+//! Each one has a plain text and an HTML template in `templates/emails`,
+//! embedded in the binary and filled in with `format!`: a template using a
+//! placeholder its email does not provide, or an email providing a value its
+//! template does not use, is a compile error.
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailMessage;
+use crate::i18n::{fill, Catalogue};
+use htmlescape::encode_minimal;
+
+macro_rules! render {
+    ($file:literal, $($arg:tt)*) => {
+        format!(include_str!(concat!("../templates/emails/", $file)), $($arg)*)
+    };
+}
+
+/// An email with a subject and a body in both formats.
+pub trait TransactionalEmail {
+    fn subject(&self) -> String;
+    fn html(&self) -> String;
+    fn text(&self) -> String;
+
+    fn message(&self, recipient: SubscriberEmail) -> EmailMessage {
+        EmailMessage::new(recipient, self.subject())
+            .html(self.html())
+            .text(self.text())
+    }
+}
+
+/// Sent on subscription, to check the address belongs to the subscriber.
+/// Written in the subscriber's language.
+pub struct ConfirmationEmail<'a> {
+    pub confirmation_link: &'a str,
+    pub catalogue: &'static Catalogue,
+}
+
+impl TransactionalEmail for ConfirmationEmail<'_> {
+    fn subject(&self) -> String {
+        self.catalogue.confirmation_subject.into()
+    }
+
+    fn html(&self) -> String {
+        render!(
+            "confirmation.html",
+            welcome = self.catalogue.confirmation_welcome,
+            call_to_action = fill(
+                self.catalogue.confirmation_call_to_action_html,
+                "confirmation_link",
+                &encode_minimal(self.confirmation_link)
+            )
+        )
+    }
+
+    fn text(&self) -> String {
+        render!(
+            "confirmation.txt",
+            welcome = self.catalogue.confirmation_welcome,
+            call_to_action = fill(
+                self.catalogue.confirmation_call_to_action_text,
+                "confirmation_link",
+                self.confirmation_link
+            )
+        )
+    }
+}
+
+/// Sent once the subscription is confirmed, in the subscriber's language.
+pub struct WelcomeEmail<'a> {
+    pub name: &'a str,
+    pub issues_link: &'a str,
+    pub catalogue: &'static Catalogue,
+}
+
+impl TransactionalEmail for WelcomeEmail<'_> {
+    fn subject(&self) -> String {
+        self.catalogue.welcome_subject.into()
+    }
+
+    fn html(&self) -> String {
+        render!(
+            "welcome.html",
+            greeting = fill(
+                self.catalogue.welcome_greeting,
+                "name",
+                &encode_minimal(self.name)
+            ),
+            confirmed = self.catalogue.welcome_confirmed,
+            archive = fill(
+                self.catalogue.welcome_archive_html,
+                "issues_link",
+                &encode_minimal(self.issues_link)
+            )
+        )
+    }
+
+    fn text(&self) -> String {
+        render!(
+            "welcome.txt",
+            greeting = fill(self.catalogue.welcome_greeting, "name", self.name),
+            confirmed = self.catalogue.welcome_confirmed,
+            archive = fill(
+                self.catalogue.welcome_archive_text,
+                "issues_link",
+                self.issues_link
+            )
+        )
+    }
+}
+
+/// Sent to a user whose password was changed, in case it was not them.
+pub struct PasswordChangedEmail<'a> {
+    pub username: &'a str,
+}
+
+impl TransactionalEmail for PasswordChangedEmail<'_> {
+    fn subject(&self) -> String {
+        "Your password has been changed".into()
+    }
+
+    fn html(&self) -> String {
+        render!(
+            "password_changed.html",
+            username = encode_minimal(self.username)
+        )
+    }
+
+    fn text(&self) -> String {
+        render!("password_changed.txt", username = self.username)
+    }
+}
+
+/// Sent to someone invited to the admin area, to choose their password.
+pub struct InvitationEmail<'a> {
+    pub username: &'a str,
+    pub inviter: &'a str,
+    pub role: &'a str,
+    pub invitation_link: &'a str,
+}
+
+impl TransactionalEmail for InvitationEmail<'_> {
+    fn subject(&self) -> String {
+        "You have been invited to our newsletter".into()
+    }
+
+    fn html(&self) -> String {
+        render!(
+            "invitation.html",
+            username = encode_minimal(self.username),
+            inviter = encode_minimal(self.inviter),
+            role = self.role,
+            invitation_link = encode_minimal(self.invitation_link)
+        )
+    }
+
+    fn text(&self) -> String {
+        render!(
+            "invitation.txt",
+            username = self.username,
+            inviter = self.inviter,
+            role = self.role,
+            invitation_link = self.invitation_link
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfirmationEmail, TransactionalEmail, WelcomeEmail};
+    use crate::domain::Locale;
+    use crate::i18n::Catalogue;
+
+    #[test]
+    fn each_format_gets_its_own_body() {
+        let email = ConfirmationEmail {
+            confirmation_link: "https://example.com/confirm?a=1&b=2",
+            catalogue: Catalogue::for_locale(&Locale::default()),
+        };
+        assert!(email
+            .html()
+            .contains(r#"<a href="https://example.com/confirm?a=1&amp;b=2">"#));
+        assert!(email
+            .text()
+            .contains("Visit https://example.com/confirm?a=1&b=2 to confirm"));
+        assert!(!email.text().contains('<'));
+    }
+
+    #[test]
+    fn the_confirmation_email_is_written_in_the_subscriber_language() {
+        let email = ConfirmationEmail {
+            confirmation_link: "https://example.com/confirm",
+            catalogue: Catalogue::for_locale(&Locale::parse("fr".into()).unwrap()),
+        };
+        assert_eq!(email.subject(), "Bienvenue !");
+        assert!(email
+            .text()
+            .contains("Rendez-vous sur https://example.com/confirm pour confirmer"));
+    }
+
+    #[test]
+    fn values_are_escaped_in_the_html_body_only() {
+        let email = WelcomeEmail {
+            name: "<script>",
+            issues_link: "https://example.com/issues",
+            catalogue: Catalogue::for_locale(&Locale::default()),
+        };
+        assert!(email.html().contains("Hi &lt;script&gt;,"));
+        assert!(email.text().contains("Hi <script>,"));
+    }
+}
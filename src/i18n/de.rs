@@ -0,0 +1,27 @@
+use super::Catalogue;
+
+pub(super) const CATALOGUE: Catalogue = Catalogue {
+    language: "de",
+    home_title: "Startseite",
+    home_welcome: "Willkommen bei unserem Newsletter!",
+    confirmation_subject: "Willkommen!",
+    confirmation_welcome: "Willkommen bei unserem Newsletter!",
+    confirmation_call_to_action_html: r#"Klicken Sie <a href="{confirmation_link}">hier</a>, um Ihr Abonnement zu bestätigen."#,
+    confirmation_call_to_action_text:
+        "Besuchen Sie {confirmation_link}, um Ihr Abonnement zu bestätigen.",
+    welcome_subject: "Sie sind dabei!",
+    welcome_greeting: "Hallo {name},",
+    welcome_confirmed: "Ihr Abonnement ist bestätigt: Die nächste Ausgabe landet in Ihrem Posteingang.",
+    welcome_archive_html: r#"Frühere Ausgaben warten <a href="{issues_link}">im Archiv</a> auf Sie."#,
+    welcome_archive_text: "Frühere Ausgaben warten unter {issues_link} auf Sie.",
+    unsubscribe_title: "Abbestellen",
+    unsubscribe_question: "Möchten Sie unseren Newsletter nicht mehr erhalten?",
+    unsubscribe_button: "Abbestellen",
+    unsubscribed: "Sie haben den Newsletter abbestellt. Sie erhalten keine weiteren Ausgaben.",
+    confirmation_title: "Bestätigung des Abonnements",
+    confirmed: "Ihr Abonnement ist bestätigt. Die nächste Ausgabe landet in Ihrem Posteingang.",
+    confirmation_link_invalid:
+        "Dieser Bestätigungslink ist ungültig. Er wurde vielleicht schon verwendet oder durch einen neueren ersetzt.",
+    confirmation_link_expired:
+        "Dieser Bestätigungslink ist abgelaufen. Melden Sie sich erneut an, um einen neuen zu erhalten.",
+};
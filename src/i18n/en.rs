@@ -0,0 +1,26 @@
+use super::Catalogue;
+
+pub(super) const CATALOGUE: Catalogue = Catalogue {
+    language: "en",
+    home_title: "Home",
+    home_welcome: "Welcome to our newsletter!",
+    confirmation_subject: "Welcome!",
+    confirmation_welcome: "Welcome to our newsletter!",
+    confirmation_call_to_action_html: r#"Click <a href="{confirmation_link}">here</a> to confirm your subscription."#,
+    confirmation_call_to_action_text: "Visit {confirmation_link} to confirm your subscription.",
+    welcome_subject: "You are in!",
+    welcome_greeting: "Hi {name},",
+    welcome_confirmed: "Your subscription is confirmed: the next issue will land in your inbox.",
+    welcome_archive_html: r#"Past issues are waiting for you <a href="{issues_link}">in the archive</a>."#,
+    welcome_archive_text: "Past issues are waiting for you at {issues_link}",
+    unsubscribe_title: "Unsubscribe",
+    unsubscribe_question: "Do you want to stop receiving our newsletter?",
+    unsubscribe_button: "Unsubscribe",
+    unsubscribed: "You have been unsubscribed. You will not receive any more issues.",
+    confirmation_title: "Subscription confirmation",
+    confirmed: "Your subscription is confirmed. The next issue will land in your inbox.",
+    confirmation_link_invalid:
+        "This confirmation link is not valid. It may have been used already, or replaced by a newer one.",
+    confirmation_link_expired:
+        "This confirmation link has expired. Subscribe again to receive a new one.",
+};
@@ -0,0 +1,27 @@
+use super::Catalogue;
+
+pub(super) const CATALOGUE: Catalogue = Catalogue {
+    language: "fr",
+    home_title: "Accueil",
+    home_welcome: "Bienvenue dans notre newsletter !",
+    confirmation_subject: "Bienvenue !",
+    confirmation_welcome: "Bienvenue dans notre newsletter !",
+    confirmation_call_to_action_html: r#"Cliquez <a href="{confirmation_link}">ici</a> pour confirmer votre inscription."#,
+    confirmation_call_to_action_text:
+        "Rendez-vous sur {confirmation_link} pour confirmer votre inscription.",
+    welcome_subject: "Vous êtes inscrit !",
+    welcome_greeting: "Bonjour {name},",
+    welcome_confirmed: "Votre inscription est confirmée : le prochain numéro arrivera dans votre boîte de réception.",
+    welcome_archive_html: r#"Les numéros précédents vous attendent <a href="{issues_link}">dans les archives</a>."#,
+    welcome_archive_text: "Les numéros précédents vous attendent sur {issues_link}",
+    unsubscribe_title: "Se désabonner",
+    unsubscribe_question: "Voulez-vous ne plus recevoir notre newsletter ?",
+    unsubscribe_button: "Se désabonner",
+    unsubscribed: "Votre désabonnement est pris en compte. Vous ne recevrez plus aucun numéro.",
+    confirmation_title: "Confirmation de l'inscription",
+    confirmed: "Votre inscription est confirmée. Le prochain numéro arrivera dans votre boîte de réception.",
+    confirmation_link_invalid:
+        "Ce lien de confirmation n'est pas valide. Il a peut-être déjà servi, ou été remplacé par un plus récent.",
+    confirmation_link_expired:
+        "Ce lien de confirmation a expiré. Inscrivez-vous de nouveau pour en recevoir un autre.",
+};
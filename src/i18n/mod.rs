@@ -0,0 +1,131 @@
+//! Message catalogues for the pages and emails subscribers see.
+//!
+//! Every locale we translate to has a `Catalogue` of its own: the compiler
+//! makes sure none of them misses a message. Messages may contain
+//! `{placeholders}`, filled in with `fill`.
+
+mod de;
+mod en;
+mod fr;
+
+use crate::domain::Locale;
+
+pub struct Catalogue {
+    /// The language of the catalogue, as in `Content-Language`.
+    pub language: &'static str,
+    pub home_title: &'static str,
+    pub home_welcome: &'static str,
+    pub confirmation_subject: &'static str,
+    pub confirmation_welcome: &'static str,
+    /// HTML, with a `{confirmation_link}` placeholder.
+    pub confirmation_call_to_action_html: &'static str,
+    /// Plain text, with a `{confirmation_link}` placeholder.
+    pub confirmation_call_to_action_text: &'static str,
+    pub welcome_subject: &'static str,
+    /// With a `{name}` placeholder.
+    pub welcome_greeting: &'static str,
+    pub welcome_confirmed: &'static str,
+    /// HTML, with an `{issues_link}` placeholder.
+    pub welcome_archive_html: &'static str,
+    /// Plain text, with an `{issues_link}` placeholder.
+    pub welcome_archive_text: &'static str,
+    pub unsubscribe_title: &'static str,
+    pub unsubscribe_question: &'static str,
+    pub unsubscribe_button: &'static str,
+    pub unsubscribed: &'static str,
+    pub confirmation_title: &'static str,
+    pub confirmed: &'static str,
+    pub confirmation_link_invalid: &'static str,
+    pub confirmation_link_expired: &'static str,
+}
+
+const CATALOGUES: [&Catalogue; 3] = [&en::CATALOGUE, &fr::CATALOGUE, &de::CATALOGUE];
+
+impl Catalogue {
+    /// The catalogue for the locale's language, English if we have none.
+    pub fn for_locale(locale: &Locale) -> &'static Catalogue {
+        Self::find(locale).unwrap_or(&en::CATALOGUE)
+    }
+
+    /// The catalogue for the first language of an `Accept-Language` header
+    /// we have one for, English if there is none.
+    pub fn negotiate(accept_language: &str) -> &'static Catalogue {
+        let mut ranges: Vec<(&str, f32)> = accept_language
+            .split(',')
+            .map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next().unwrap_or_default().trim();
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                (tag, quality)
+            })
+            .collect();
+        // Stable: ranges of equal quality keep the order they were given in
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges
+            .into_iter()
+            .filter(|(_, quality)| *quality > 0.0)
+            .filter_map(|(tag, _)| Locale::parse(tag.to_owned()).ok())
+            .find_map(|locale| Self::find(&locale))
+            .unwrap_or(&en::CATALOGUE)
+    }
+
+    fn find(locale: &Locale) -> Option<&'static Catalogue> {
+        CATALOGUES
+            .into_iter()
+            .find(|catalogue| catalogue.language == locale.language())
+    }
+}
+
+/// Replace `{placeholder}` in a message.
+pub fn fill(message: &str, placeholder: &str, value: &str) -> String {
+    message.replace(&format!("{{{}}}", placeholder), value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Catalogue, CATALOGUES};
+    use crate::domain::Locale;
+
+    #[test]
+    fn regional_locales_get_the_catalogue_of_their_language() {
+        let locale = Locale::parse("fr-CA".into()).unwrap();
+        assert_eq!(Catalogue::for_locale(&locale).language, "fr");
+    }
+
+    #[test]
+    fn untranslated_locales_fall_back_to_english() {
+        let locale = Locale::parse("ja".into()).unwrap();
+        assert_eq!(Catalogue::for_locale(&locale).language, "en");
+        assert_eq!(Catalogue::negotiate("ja, *;q=0.1").language, "en");
+        assert_eq!(Catalogue::negotiate("").language, "en");
+    }
+
+    #[test]
+    fn the_preferred_translated_language_wins() {
+        assert_eq!(
+            Catalogue::negotiate("ja, en;q=0.5, de;q=0.8").language,
+            "de"
+        );
+        assert_eq!(Catalogue::negotiate("fr;q=0, en").language, "en");
+    }
+
+    #[test]
+    fn every_catalogue_keeps_the_placeholders() {
+        for catalogue in CATALOGUES {
+            assert!(catalogue
+                .confirmation_call_to_action_html
+                .contains(r#"href="{confirmation_link}""#));
+            assert!(catalogue
+                .confirmation_call_to_action_text
+                .contains("{confirmation_link}"));
+            assert!(catalogue.welcome_greeting.contains("{name}"));
+            assert!(catalogue
+                .welcome_archive_html
+                .contains(r#"href="{issues_link}""#));
+            assert!(catalogue.welcome_archive_text.contains("{issues_link}"));
+        }
+    }
+}
@@ -0,0 +1,4 @@
+mod key;
+mod persistence;
+pub use key::IdempotencyKey;
+pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
@@ -0,0 +1,742 @@
+use crate::configuration::{IssueDeliverySettings, Settings};
+use crate::domain::{Locale, SubscriberEmail};
+use crate::email_client::{EmailClient, EmailClientError, EmailMessage, SentEmail};
+use crate::startup::get_connection_pool;
+use crate::subscriber_links::SubscriberLinks;
+use crate::templating::{Recipient, Template};
+use rand::Rng;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt::Write;
+use std::time::Duration;
+use tracing::Span;
+use uuid::Uuid;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Deliver a batch of due tasks with as few calls to the email provider as
+/// possible. Every task is settled on its own: a recipient rejected by the
+/// provider is retried or dead-lettered without affecting the others, and
+/// failing to record the outcome of one task leaves the rest of the batch
+/// untouched.
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    settings: &IssueDeliverySettings,
+    links: &SubscriberLinks,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let tasks = claim_tasks(pool, settings.batch_size).await?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    Span::current().record("n_tasks", tasks.len());
+    let mut issues = HashMap::new();
+    let mut prepared = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let settlement = match prepare_email(pool, links, &mut issues, &task).await {
+            Ok(Preparation::Ready(email)) => {
+                prepared.push((task, email));
+                continue;
+            }
+            Ok(Preparation::Skip) => Settlement::Skipped,
+            Ok(Preparation::Invalid(e)) => Settlement::DeadLetter {
+                n_attempts: task.n_attempts + 1,
+                last_error: e,
+                error_code: None,
+            },
+            // The claim on the task expires and it is tried again later
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to prepare an issue for a confirmed subscriber.",
+                );
+                continue;
+            }
+        };
+        settle(pool, &task, settlement).await;
+    }
+    let (tasks, batch): (Vec<_>, Vec<_>) = prepared.into_iter().unzip();
+    let outcomes = email_client.send_email_batch(&batch).await;
+    for (task, outcome) in tasks.iter().zip(outcomes) {
+        let n_attempts = task.n_attempts + 1;
+        let e = match outcome {
+            Ok(sent) => {
+                settle(pool, task, Settlement::Delivered(sent)).await;
+                continue;
+            }
+            Err(e) => e,
+        };
+        let failure = DeliveryFailure::classify(&e);
+        tracing::error!(
+            newsletter_issue_id = %task.newsletter_issue_id,
+            subscriber_email = %task.subscriber_email,
+            n_attempts = task.n_attempts,
+            error.cause_chain = ?e,
+            error.message = %e,
+            failure = ?failure,
+            "Failed to deliver issue to a confirmed subscriber.",
+        );
+        let settlement =
+            if failure == DeliveryFailure::Permanent || n_attempts >= settings.max_attempts {
+                Settlement::DeadLetter {
+                    n_attempts,
+                    last_error: e.to_string(),
+                    error_code: e.error_code(),
+                }
+            } else {
+                Settlement::Retry {
+                    n_attempts,
+                    retry_in: retry_backoff(settings, n_attempts),
+                }
+            };
+        settle(pool, task, settlement).await;
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// What becomes of a task once we are done with it.
+enum Settlement {
+    Delivered(SentEmail),
+    Skipped,
+    Retry {
+        n_attempts: i16,
+        retry_in: Duration,
+    },
+    DeadLetter {
+        n_attempts: i16,
+        last_error: String,
+        error_code: Option<i64>,
+    },
+}
+
+/// Record the outcome of a task in its own transaction. Errors are logged
+/// rather than returned: the claim on the task expires and it is picked up
+/// again, which may send the email twice but never drops it.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=%task.newsletter_issue_id,
+        subscriber_email=%task.subscriber_email,
+    )
+)]
+async fn settle(pool: &PgPool, task: &DeliveryTask, settlement: Settlement) {
+    let result = async {
+        let mut transaction = pool.begin().await?;
+        match settlement {
+            Settlement::Delivered(sent) => {
+                record_delivery(&mut transaction, task, &sent).await?;
+                delete_task(&mut transaction, task).await?;
+            }
+            Settlement::Skipped => delete_task(&mut transaction, task).await?,
+            Settlement::Retry {
+                n_attempts,
+                retry_in,
+            } => schedule_retry(&mut transaction, task, n_attempts, retry_in).await?,
+            Settlement::DeadLetter {
+                n_attempts,
+                last_error,
+                error_code,
+            } => {
+                move_to_dead_letters(&mut transaction, task, n_attempts, &last_error, error_code)
+                    .await?
+            }
+        }
+        transaction.commit().await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to record the outcome of a delivery task.",
+        );
+    }
+}
+
+enum Preparation {
+    /// The issue rendered for the subscriber, ready to be sent.
+    Ready(EmailMessage),
+    /// Nothing to send any more: the task can be dropped.
+    Skip,
+    /// The email can never be sent: the task goes to the dead letters.
+    Invalid(String),
+}
+
+/// The templates of an issue, parsed once per batch.
+struct IssueTemplates {
+    title: String,
+    html: Template,
+    text: Template,
+    tracking_enabled: bool,
+}
+
+/// An issue and its translations.
+struct IssueVersions {
+    original: IssueTemplates,
+    translations: Vec<(Locale, IssueTemplates)>,
+}
+
+impl IssueVersions {
+    /// Bodies are checked when the issue is submitted, but issues published
+    /// before placeholders existed may contain stray braces.
+    fn parse(
+        issue: NewsletterIssue,
+        translations: Vec<IssueTranslation>,
+    ) -> Result<IssueVersions, String> {
+        let parse = |title: String, text_content: &str, html_content: &str| {
+            Ok::<_, String>(IssueTemplates {
+                title,
+                html: Template::parse(html_content)?,
+                text: Template::parse(text_content)?,
+                tracking_enabled: issue.tracking_enabled,
+            })
+        };
+        let mut parsed = Vec::with_capacity(translations.len());
+        for translation in translations {
+            let locale = Locale::parse(translation.locale)?;
+            let templates = parse(
+                translation.title,
+                &translation.text_content,
+                &translation.html_content,
+            )?;
+            parsed.push((locale, templates));
+        }
+        Ok(IssueVersions {
+            original: parse(issue.title, &issue.text_content, &issue.html_content)?,
+            translations: parsed,
+        })
+    }
+
+    /// The translation to the subscriber's locale, or else to their language,
+    /// or else the original issue.
+    fn for_locale(&self, locale: &Locale) -> &IssueTemplates {
+        self.translations
+            .iter()
+            .find(|(l, _)| l == locale)
+            .or_else(|| {
+                self.translations
+                    .iter()
+                    .find(|(l, _)| l.language() == locale.language())
+            })
+            .map_or(&self.original, |(_, templates)| templates)
+    }
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=%task.newsletter_issue_id,
+        subscriber_email=%task.subscriber_email,
+        n_attempts=task.n_attempts
+    )
+)]
+async fn prepare_email(
+    pool: &PgPool,
+    links: &SubscriberLinks,
+    issues: &mut HashMap<Uuid, Result<IssueVersions, String>>,
+    task: &DeliveryTask,
+) -> Result<Preparation, anyhow::Error> {
+    let email = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid",
+            );
+            return Ok(Preparation::Invalid(e));
+        }
+    };
+    // The subscriber may have left the issue's lists after it was published
+    let Some(subscriber) =
+        get_confirmed_subscriber(pool, &task.subscriber_email, task.newsletter_issue_id).await?
+    else {
+        tracing::info!("Skipping a subscriber who is no longer confirmed.");
+        return Ok(Preparation::Skip);
+    };
+    let issue = match issues.entry(task.newsletter_issue_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let issue = get_issue(pool, task.newsletter_issue_id).await?;
+            let translations = get_issue_translations(pool, task.newsletter_issue_id).await?;
+            entry.insert(IssueVersions::parse(issue, translations))
+        }
+    };
+    let issue = match issue {
+        Ok(issue) => issue.for_locale(&subscriber.locale),
+        Err(e) => {
+            tracing::error!(error.message = %e, "The issue body is not a valid template");
+            return Ok(Preparation::Invalid(e.clone()));
+        }
+    };
+    let unsubscribe_link = links.unsubscribe(subscriber.id);
+    let preferences_link = links.preferences(subscriber.id);
+    let web_version_link = links.web_version(task.newsletter_issue_id);
+    let recipient = Recipient {
+        name: &subscriber.name,
+        email: email.as_ref(),
+        unsubscribe_url: unsubscribe_link.as_str(),
+        preferences_url: preferences_link.as_str(),
+    };
+    let mut body = issue.html.render_html(&recipient);
+    if issue.tracking_enabled {
+        // Only the links of the issue itself are tracked, not our own
+        body = track_links(&body, |url| {
+            (url != unsubscribe_link.as_str() && url != preferences_link.as_str()).then(|| {
+                links
+                    .click(task.newsletter_issue_id, subscriber.id, url)
+                    .to_string()
+            })
+        });
+        write!(
+            body,
+            r#"<img src="{}" width="1" height="1" alt="">"#,
+            links.open_pixel(task.newsletter_issue_id, subscriber.id)
+        )
+        .unwrap();
+    }
+    let html_content = format!(
+        "<p><a href=\"{}\">View this issue in your browser</a></p>{}<hr /><p><a href=\"{}\">Manage your preferences</a> or <a href=\"{}\">unsubscribe</a> from this newsletter.</p>",
+        web_version_link,
+        body,
+        preferences_link,
+        unsubscribe_link
+    );
+    let text_content = format!(
+        "View this issue in your browser: {}\n\n{}\n\n--\nManage your preferences: {}\nUnsubscribe from this newsletter: {}",
+        web_version_link,
+        issue.text.render_text(&recipient),
+        preferences_link,
+        unsubscribe_link
+    );
+    // RFC 8058: mail clients can unsubscribe with a single POST to the link
+    let message = EmailMessage::new(email, issue.title.clone())
+        .html(html_content)
+        .text(text_content)
+        .header("List-Unsubscribe", format!("<{}>", unsubscribe_link))
+        .header("List-Unsubscribe-Post", "List-Unsubscribe=One-Click");
+    Ok(Preparation::Ready(message))
+}
+
+/// Replace the target of every http(s) link in `html` with whatever `track`
+/// returns for it, leaving the links it returns `None` for untouched.
+fn track_links(html: &str, track: impl Fn(&str) -> Option<String>) -> String {
+    const HREF: &str = "href=\"";
+    let mut tracked = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(HREF) {
+        let (before, after) = rest.split_at(start + HREF.len());
+        tracked.push_str(before);
+        let Some(end) = after.find('"') else {
+            rest = after;
+            break;
+        };
+        let value = &after[..end];
+        let link = htmlescape::decode_html(value)
+            .ok()
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+            .and_then(|url| track(&url));
+        // The value sits between double quotes, which this escapes
+        match link {
+            Some(link) => tracked.push_str(&htmlescape::encode_minimal(&link)),
+            None => tracked.push_str(value),
+        }
+        rest = &after[end..];
+    }
+    tracked.push_str(rest);
+    tracked
+}
+
+#[derive(Debug, PartialEq)]
+enum DeliveryFailure {
+    Transient,
+    Permanent,
+}
+
+impl DeliveryFailure {
+    /// Timeouts, connection errors, rate limiting and provider errors are
+    /// worth retrying; rejected emails and recipients are not.
+    fn classify(e: &EmailClientError) -> Self {
+        if e.is_permanent() {
+            Self::Permanent
+        } else {
+            Self::Transient
+        }
+    }
+}
+
+/// Exponential backoff with jitter: the n-th attempt waits between half and
+/// the whole of `base * 2^(n - 1)`, capped at `max_backoff`.
+fn retry_backoff(settings: &IssueDeliverySettings, n_attempts: i16) -> Duration {
+    let exponent = u32::try_from(n_attempts.saturating_sub(1)).unwrap_or(0);
+    let backoff = settings
+        .base_backoff()
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(settings.max_backoff());
+    let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+    backoff.mul_f64(1.0 - jitter)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i16,
+}
+
+/// How long a worker has to settle the tasks it claimed before they are due
+/// again, should it die or lose its database connection along the way.
+const CLAIM_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Claim up to `batch_size` tasks in the queue that are due for execution, by
+/// postponing them for `CLAIM_DURATION`.
+///
+/// `SKIP LOCKED` lets several workers poll the same queue: rows another
+/// worker is claiming are ignored instead of blocking until it commits.
+#[tracing::instrument(skip_all)]
+async fn claim_tasks(pool: &PgPool, batch_size: i64) -> Result<Vec<DeliveryTask>, anyhow::Error> {
+    let tasks = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = now() + $2 * interval '1 millisecond'
+        WHERE (newsletter_issue_id, subscriber_email) IN (
+            SELECT newsletter_issue_id, subscriber_email
+            FROM issue_delivery_queue
+            WHERE execute_after <= now()
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT $1
+        )
+        RETURNING newsletter_issue_id, subscriber_email, n_attempts
+        "#,
+        batch_size,
+        CLAIM_DURATION.as_millis() as f64
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(tasks)
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(retry_in = ?retry_in))]
+async fn schedule_retry(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i16,
+    retry_in: Duration,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_attempts = $3,
+            execute_after = now() + $4 * interval '1 millisecond'
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        retry_in.as_millis() as f64
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn move_to_dead_letters(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i16,
+    last_error: &str,
+    error_code: Option<i64>,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_dead_letters (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            last_error,
+            error_code,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_attempts = EXCLUDED.n_attempts,
+            last_error = EXCLUDED.last_error,
+            error_code = EXCLUDED.error_code,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        last_error,
+        error_code
+    );
+    transaction.execute(query).await?;
+    delete_task(transaction, task).await
+}
+
+/// Keep the provider's identifier of the email, to make sense of the events
+/// it reports about it later on.
+#[tracing::instrument(skip_all)]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    sent: &SentEmail,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            message_id,
+            submitted_at
+        )
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            message_id = EXCLUDED.message_id,
+            submitted_at = EXCLUDED.submitted_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        sent.message_id,
+        sent.submitted_at
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    name: String,
+    locale: Locale,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber(
+    pool: &PgPool,
+    email: &str,
+    issue_id: Uuid,
+) -> Result<Option<ConfirmedSubscriber>, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT s.id, s.name, s.locale
+        FROM subscriptions s
+        WHERE
+            s.email = $1 AND
+            s.status = 'confirmed' AND
+            EXISTS (
+                SELECT 1
+                FROM list_subscriptions l
+                JOIN newsletter_issue_lists i USING (list_id)
+                WHERE
+                    l.subscriber_id = s.id AND
+                    l.status = 'confirmed' AND
+                    i.newsletter_issue_id = $2
+            )
+        "#,
+        email,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| ConfirmedSubscriber {
+        id: r.id,
+        name: r.name,
+        locale: Locale::parse(r.locale).unwrap_or_default(),
+    });
+    Ok(subscriber)
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    tracking_enabled: bool,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content, tracking_enabled
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(issue)
+}
+
+struct IssueTranslation {
+    locale: String,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue_translations(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<IssueTranslation>, anyhow::Error> {
+    let translations = sqlx::query_as!(
+        IssueTranslation,
+        r#"
+        SELECT locale, title, text_content, html_content
+        FROM newsletter_issue_translations
+        WHERE
+            newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(translations)
+}
+
+async fn worker_loop(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    settings: &IssueDeliverySettings,
+    links: &SubscriberLinks,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(pool, email_client, settings, links).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Run as many loops as the email client lets calls be in flight: a loop
+/// sends its whole batch in as few calls as the provider allows, often one,
+/// so a single loop would never make use of the others.
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let n_loops = configuration.email_client.max_concurrency.max(1);
+    let email_client = configuration.email_client.client();
+    let links = configuration.application.subscriber_links();
+    let settings = configuration.issue_delivery;
+    let loops =
+        (0..n_loops).map(|_| worker_loop(&connection_pool, &email_client, &settings, &links));
+    futures::future::try_join_all(loops).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_backoff, track_links, DeliveryFailure};
+    use crate::configuration::IssueDeliverySettings;
+    use crate::email_client::EmailClientError;
+    use std::time::Duration;
+
+    fn settings() -> IssueDeliverySettings {
+        IssueDeliverySettings {
+            max_attempts: 5,
+            batch_size: 100,
+            base_backoff_milliseconds: 1000,
+            max_backoff_milliseconds: 10_000,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_every_attempt() {
+        for (n_attempts, ceiling) in [(1, 1000), (2, 2000), (3, 4000), (4, 8000)] {
+            let backoff = retry_backoff(&settings(), n_attempts);
+            assert!(backoff <= Duration::from_millis(ceiling));
+            assert!(backoff >= Duration::from_millis(ceiling / 2));
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let backoff = retry_backoff(&settings(), i16::MAX);
+        assert!(backoff <= Duration::from_millis(10_000));
+        assert!(backoff >= Duration::from_millis(5_000));
+    }
+
+    #[test]
+    fn invalid_recipients_are_permanent() {
+        let e = EmailClientError::InvalidRecipient {
+            error_code: Some(406),
+            message: "inactive recipient".into(),
+        };
+        assert_eq!(DeliveryFailure::classify(&e), DeliveryFailure::Permanent);
+    }
+
+    #[test]
+    fn timeouts_are_transient() {
+        assert_eq!(
+            DeliveryFailure::classify(&EmailClientError::Timeout),
+            DeliveryFailure::Transient
+        );
+    }
+
+    #[test]
+    fn rejections_are_classified_by_the_provider() {
+        for (permanent, expected) in [
+            (false, DeliveryFailure::Transient),
+            (true, DeliveryFailure::Permanent),
+        ] {
+            let e = EmailClientError::Rejected {
+                status: Some(451),
+                error_code: None,
+                message: "rejected".into(),
+                permanent,
+            };
+            assert_eq!(DeliveryFailure::classify(&e), expected);
+        }
+    }
+
+    #[test]
+    fn only_http_links_are_tracked() {
+        let html = r#"<a href="https://example.com/?a=1&amp;b=2">x</a> <a href="mailto:me@example.com">y</a> <a href="https://example.com/skip">z</a>"#;
+        let tracked = track_links(html, |url| {
+            (url != "https://example.com/skip").then(|| format!("https://t.example/?u={}", url))
+        });
+        assert_eq!(
+            tracked,
+            r#"<a href="https://t.example/?u=https://example.com/?a=1&amp;b=2">x</a> <a href="mailto:me@example.com">y</a> <a href="https://example.com/skip">z</a>"#
+        );
+    }
+}
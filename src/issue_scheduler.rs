@@ -0,0 +1,72 @@
+use crate::configuration::Settings;
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::routes::enqueue_delivery_tasks;
+use crate::startup::get_connection_pool;
+use sqlx::{Executor, PgPool};
+use std::time::Duration;
+use tracing::{field::display, Span};
+
+/// Hand the next scheduled issue that is due over to the delivery worker.
+///
+/// Subscribers are resolved at release time, so people who joined or left
+/// the issue's lists after it was scheduled are taken into account.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_release_issue(pool: &PgPool) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM scheduled_issues
+        WHERE send_at <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(issue) = issue else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let issue_id = issue.newsletter_issue_id;
+    Span::current().record("newsletter_issue_id", display(issue_id));
+    enqueue_delivery_tasks(&mut transaction, issue_id).await?;
+    let query = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM scheduled_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn scheduler_loop(pool: PgPool) -> Result<(), anyhow::Error> {
+    loop {
+        match try_release_issue(&pool).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_scheduler_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    scheduler_loop(connection_pool).await
+}
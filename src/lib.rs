@@ -1,9 +1,16 @@
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_templates;
+pub mod i18n;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
 pub mod routes;
 pub mod startup;
+pub mod subscriber_links;
 pub mod telemetry;
 pub mod authentication;
 pub mod session_state;
+pub mod templating;
 pub mod utils;
\ No newline at end of file
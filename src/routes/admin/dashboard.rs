@@ -1,16 +1,56 @@
-use crate::authentication::UserId;
+use crate::authentication::{Role, UserId};
 use crate::utils::e500;
 use actix_web::{http::header::ContentType, web, HttpResponse};
 use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
 use sqlx::PgPool;
+use std::fmt::Write;
 use uuid::Uuid;
 
+struct IssueRow {
+    newsletter_issue_id: Uuid,
+    title: String,
+    author: Option<String>,
+    published_at: Option<DateTime<Utc>>,
+}
+
 pub async fn admin_dashboard(
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
+    let users_html = if *role == Role::Owner {
+        r#"<li>
+                    <a href="/admin/users">Users</a>
+                </li>"#
+    } else {
+        ""
+    };
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let mut issues_html = String::new();
+    for issue in get_issues(&pool).await.map_err(e500)? {
+        let title = encode_minimal(&issue.title);
+        let (title, published_at) = match issue.published_at {
+            Some(published_at) => (
+                format!(
+                    r#"<a href="/issues/{}">{}</a>"#,
+                    issue.newsletter_issue_id, title
+                ),
+                published_at.to_rfc3339(),
+            ),
+            None => (title, "Scheduled".into()),
+        };
+        writeln!(
+            issues_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            title,
+            encode_minimal(issue.author.as_deref().unwrap_or("-")),
+            published_at,
+        )
+        .unwrap();
+    }
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -31,12 +71,39 @@ pub async fn admin_dashboard(
                 <li>
                     <a href="/admin/password">Change password</a>
                 </li>
+                <li>
+                    <a href="/admin/email">Change email</a>
+                </li>
+                <li>
+                    <a href="/admin/dead_letters">Failed deliveries</a>
+                </li>
+                <li>
+                    <a href="/admin/newsletters">Send a newsletter issue</a>
+                </li>
+                <li>
+                    <a href="/admin/drafts">Drafts</a>
+                </li>
+                <li>
+                    <a href="/admin/scheduled_issues">Scheduled issues</a>
+                </li>
+                {users_html}
                 <li> 
                     <form name="logoutForm" action="/admin/logout" method="post"> 
                         <input type="submit" value="Logout"> 
                     </form> 
                 </li>
             </ol>
+            <p>
+                Issues:
+            </p>
+            <table>
+                <tr>
+                    <th>Title</th>
+                    <th>Author</th>
+                    <th>Published at</th>
+                </tr>
+                {issues_html}
+            </table>
         </body>
         </html>"#
         )))
@@ -57,3 +124,24 @@ pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow
     .context("Failed to perform a query to retrieve a username.")?;
     Ok(row.username)
 }
+
+#[tracing::instrument(name = "Get issues", skip(pool))]
+async fn get_issues(pool: &PgPool) -> Result<Vec<IssueRow>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueRow,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            u.username AS "author?",
+            i.published_at
+        FROM newsletter_issues i
+        LEFT JOIN users u ON u.user_id = i.author_user_id
+        ORDER BY i.published_at DESC NULLS FIRST
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the newsletter issues.")?;
+    Ok(issues)
+}
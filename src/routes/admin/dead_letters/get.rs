@@ -0,0 +1,120 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i16,
+    last_error: String,
+    error_code: Option<i64>,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn dead_letters(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+    let mut rows_html = String::new();
+    for dead_letter in get_dead_letters(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{subscriber_email}</td>
+                <td>{n_attempts}</td>
+                <td>{last_error}</td>
+                <td>{error_code}</td>
+                <td>{failed_at}</td>
+                <td>
+                    <form action="/admin/dead_letters/replay" method="post">
+                        <input type="hidden" name="newsletter_issue_id" value="{newsletter_issue_id}">
+                        <input type="hidden" name="subscriber_email" value="{subscriber_email_attribute}">
+                        <button type="submit">Replay</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = htmlescape::encode_minimal(&dead_letter.title),
+            subscriber_email = htmlescape::encode_minimal(&dead_letter.subscriber_email),
+            subscriber_email_attribute = htmlescape::encode_attribute(&dead_letter.subscriber_email),
+            n_attempts = dead_letter.n_attempts,
+            last_error = htmlescape::encode_minimal(&dead_letter.last_error),
+            error_code = dead_letter
+                .error_code
+                .map(|code| code.to_string())
+                .unwrap_or_default(),
+            failed_at = dead_letter.failed_at.to_rfc3339(),
+            newsletter_issue_id = dead_letter.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Failed deliveries</title>
+        </head>
+        <body>
+            {msg_html}
+            <table>
+                <tr>
+                    <th>Issue</th>
+                    <th>Subscriber</th>
+                    <th>Attempts</th>
+                    <th>Last error</th>
+                    <th>Error code</th>
+                    <th>Failed at</th>
+                    <th></th>
+                </tr>
+                {rows_html}
+            </table>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#
+        )))
+}
+
+#[tracing::instrument(name = "Get dead letters", skip(pool))]
+async fn get_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, anyhow::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT
+            d.newsletter_issue_id,
+            i.title,
+            d.subscriber_email,
+            d.n_attempts,
+            d.last_error,
+            d.error_code,
+            d.failed_at
+        FROM issue_delivery_dead_letters d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        ORDER BY d.failed_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve failed deliveries.")?;
+    Ok(dead_letters)
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::dead_letters;
+mod post;
+pub use post::replay_dead_letter;
@@ -0,0 +1,78 @@
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+pub async fn replay_dead_letter(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let replayed = requeue_dead_letter(&pool, form.newsletter_issue_id, &form.subscriber_email)
+        .await
+        .map_err(e500)?;
+    if replayed {
+        FlashMessage::info(format!(
+            "The delivery to {} has been queued again.",
+            form.subscriber_email
+        ))
+        .send();
+    } else {
+        FlashMessage::error("The failed delivery could not be found.").send();
+    }
+    Ok(see_other("/admin/dead_letters"))
+}
+
+/// Move a dead letter back into the delivery queue with a fresh attempt budget.
+#[tracing::instrument(name = "Requeue dead letter", skip(pool))]
+async fn requeue_dead_letter(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection")?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_dead_letters
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    );
+    let n_deleted_rows = transaction.execute(query).await?.rows_affected();
+    if n_deleted_rows == 0 {
+        return Ok(false);
+    }
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            execute_after
+        )
+        VALUES ($1, $2, 0, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    );
+    transaction.execute(query).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to replay a failed delivery")?;
+    Ok(true)
+}
@@ -0,0 +1,153 @@
+use super::get_draft;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct DraftSummary {
+    draft_id: Uuid,
+    title: String,
+    updated_at: DateTime<Utc>,
+}
+
+pub async fn drafts(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut rows_html = String::new();
+    for draft in get_drafts(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/drafts/{draft_id}">{title}</a></td>
+                <td>{updated_at}</td>
+            </tr>"#,
+            draft_id = draft.draft_id,
+            title = encode_minimal(&draft.title),
+            updated_at = draft.updated_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Drafts</title>
+        </head>
+        <body>
+            {msg_html}
+            <form action="/admin/drafts" method="post">
+                <label>
+                    Title <input type="text" placeholder="Enter the issue title" name="title">
+                </label>
+                <button type="submit">New draft</button>
+            </form>
+            <table>
+                <tr>
+                    <th>Title</th>
+                    <th>Last edited</th>
+                </tr>
+                {rows_html}
+            </table>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#
+        )))
+}
+
+pub async fn draft_form(
+    draft_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(draft) = get_draft(&pool, *draft_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Edit draft</title>
+        </head>
+        <body>
+            {msg_html}
+            <form action="/admin/drafts/{draft_id}" method="post">
+                <label>
+                    Title <input type="text" name="title" value="{title}">
+                </label>
+                <br>
+                <label>
+                    HTML content <textarea name="html_content" rows="20" cols="80">{html_content}</textarea>
+                </label>
+                <br>
+                <label>
+                    Plain text content <textarea name="text_content" rows="20" cols="80">{text_content}</textarea>
+                </label>
+                <br>
+                <button type="submit">Save</button>
+            </form>
+            <p><a href="/admin/drafts/{draft_id}/preview" target="_blank">Preview</a></p>
+            <form action="/admin/drafts/{draft_id}/test" method="post">
+                <label>
+                    Send a test to <input type="email" name="email">
+                </label>
+                <button type="submit">Send test</button>
+            </form>
+            <form action="/admin/drafts/{draft_id}/publish" method="post">
+                <label>
+                    Lists <input type="text" name="lists" placeholder="newsletter">
+                </label>
+                <label>
+                    <input type="checkbox" name="tracking"> Track opens and clicks
+                </label>
+                <button type="submit">Publish to every subscriber</button>
+            </form>
+            <p><a href="/admin/drafts">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+            draft_id = draft.draft_id,
+            title = encode_attribute(&draft.title),
+            html_content = encode_minimal(&draft.html_content),
+            text_content = encode_minimal(&draft.text_content),
+        )))
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<DraftSummary>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT draft_id, title, updated_at
+        FROM newsletter_drafts
+        ORDER BY updated_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve drafts.")?;
+    Ok(drafts)
+}
@@ -0,0 +1,38 @@
+mod get;
+pub use get::{draft_form, drafts};
+mod post;
+pub use post::{create_draft, update_draft};
+mod preview;
+pub use preview::preview_draft;
+mod publish;
+pub use publish::publish_draft;
+mod send_test;
+pub use send_test::send_test_draft;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct Draft {
+    draft_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(name = "Get draft", skip(pool))]
+async fn get_draft(pool: &PgPool, draft_id: Uuid) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT draft_id, title, text_content, html_content
+        FROM newsletter_drafts
+        WHERE draft_id = $1
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the draft.")?;
+    Ok(draft)
+}
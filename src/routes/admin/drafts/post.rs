@@ -0,0 +1,101 @@
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct NewDraftFormData {
+    title: String,
+}
+
+pub async fn create_draft(
+    form: web::Form<NewDraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if form.title.trim().is_empty() {
+        FlashMessage::error("A draft needs a title.").send();
+        return Ok(see_other("/admin/drafts"));
+    }
+    let draft_id = insert_draft(&pool, &form.title).await.map_err(e500)?;
+    Ok(see_other(&format!("/admin/drafts/{}", draft_id)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    html_content: String,
+    text_content: String,
+}
+
+pub async fn update_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    if form.title.trim().is_empty() {
+        FlashMessage::error("A draft needs a title.").send();
+        return Ok(see_other(&format!("/admin/drafts/{}", draft_id)));
+    }
+    let updated = save_draft(&pool, draft_id, &form).await.map_err(e500)?;
+    if !updated {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", draft_id)))
+}
+
+#[tracing::instrument(name = "Insert draft", skip(pool))]
+async fn insert_draft(pool: &PgPool, title: &str) -> Result<Uuid, anyhow::Error> {
+    let draft_id = Uuid::new_v4();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id,
+            title,
+            text_content,
+            html_content,
+            created_at,
+            updated_at
+        )
+        VALUES ($1, $2, '', '', now(), now())
+        "#,
+        draft_id,
+        title
+    );
+    pool.execute(query)
+        .await
+        .context("Failed to store the new draft")?;
+    Ok(draft_id)
+}
+
+#[tracing::instrument(name = "Save draft", skip(pool, form))]
+async fn save_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+    form: &DraftFormData,
+) -> Result<bool, anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            updated_at = now()
+        WHERE draft_id = $1
+        "#,
+        draft_id,
+        form.title,
+        form.text_content,
+        form.html_content
+    );
+    let n_updated_rows = pool
+        .execute(query)
+        .await
+        .context("Failed to save the draft")?
+        .rows_affected();
+    Ok(n_updated_rows > 0)
+}
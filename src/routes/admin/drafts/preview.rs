@@ -0,0 +1,20 @@
+use super::get_draft;
+use crate::templating::Template;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Render the HTML body of the draft as subscribers will see it.
+pub async fn preview_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(draft) = get_draft(&pool, *draft_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(Template::render_web_html(&draft.html_content)))
+}
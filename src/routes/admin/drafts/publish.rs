@@ -0,0 +1,118 @@
+use crate::authentication::UserId;
+use crate::routes::{
+    check_templates, enqueue_delivery_tasks, get_list_ids, insert_newsletter_issue,
+    insert_newsletter_issue_lists, list_slugs_from_field, PublishError,
+};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    /// Comma-separated list slugs, our original newsletter if empty.
+    #[serde(default)]
+    lists: String,
+    /// Present when the "Track opens and clicks" box is ticked.
+    tracking: Option<String>,
+}
+
+struct PublishedDraft {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Turn the draft into an issue and queue it for every confirmed subscriber
+/// of the chosen lists.
+#[tracing::instrument(name = "Publish draft", skip(form, pool, user_id))]
+pub async fn publish_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let draft_page = format!("/admin/drafts/{}", draft_id);
+    let list_slugs = match list_slugs_from_field(&form.lists) {
+        Ok(list_slugs) => list_slugs,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&draft_page));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection")
+        .map_err(e500)?;
+    // Deleting the draft up front makes publishing it twice impossible
+    let Some(draft) = delete_draft(&mut transaction, draft_id)
+        .await
+        .map_err(e500)?
+    else {
+        FlashMessage::error("The draft does not exist or has already been published.").send();
+        return Ok(see_other("/admin/drafts"));
+    };
+    if let Err(e) = check_templates(&draft.text_content, &draft.html_content) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&draft_page));
+    }
+    let list_ids = match get_list_ids(&mut transaction, &list_slugs).await {
+        Ok(list_ids) => list_ids,
+        Err(PublishError::ValidationError(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&draft_page));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        **user_id,
+        &draft.title,
+        &draft.text_content,
+        &draft.html_content,
+        Some(Utc::now()),
+        form.tracking.is_some(),
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    insert_newsletter_issue_lists(&mut transaction, issue_id, &list_ids)
+        .await
+        .context("Failed to store the lists of the newsletter issue")
+        .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a draft")
+        .map_err(e500)?;
+    FlashMessage::info(format!("The issue \"{}\" has been published.", draft.title)).send();
+    Ok(see_other("/admin/drafts"))
+}
+
+#[tracing::instrument(name = "Delete draft", skip(transaction))]
+async fn delete_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+) -> Result<Option<PublishedDraft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        PublishedDraft,
+        r#"
+        DELETE FROM newsletter_drafts
+        WHERE draft_id = $1
+        RETURNING title, text_content, html_content
+        "#,
+        draft_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to delete the draft.")?;
+    Ok(draft)
+}
@@ -0,0 +1,76 @@
+use super::get_draft;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::templating::{Recipient, Template};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// Send the draft to a single address, bypassing the delivery queue: the
+/// test has nothing to do with our subscribers.
+#[tracing::instrument(name = "Send test draft", skip(form, pool, email_client))]
+pub async fn send_test_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let draft_page = format!("/admin/drafts/{}", draft_id);
+    let Some(draft) = get_draft(&pool, draft_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let recipient = match SubscriberEmail::parse(form.0.email) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&draft_page));
+        }
+    };
+    let templates = Template::parse(&draft.html_content)
+        .and_then(|html| Ok((html, Template::parse(&draft.text_content)?)));
+    let (html_template, text_template) = match templates {
+        Ok(templates) => templates,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&draft_page));
+        }
+    };
+    // The test address is not a subscriber: its links lead nowhere
+    let sample = Recipient {
+        name: "Test Subscriber",
+        email: recipient.as_ref(),
+        unsubscribe_url: "#",
+        preferences_url: "#",
+    };
+    let subject = format!("[TEST] {}", draft.title);
+    match email_client
+        .send_email(
+            &recipient,
+            &subject,
+            &html_template.render_html(&sample),
+            &text_template.render_text(&sample),
+        )
+        .await
+    {
+        Ok(_) => {
+            FlashMessage::info(format!("A test email has been sent to {}.", recipient)).send()
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a test email",
+            );
+            FlashMessage::error("Failed to send the test email.").send()
+        }
+    }
+    Ok(see_other(&draft_page))
+}
@@ -0,0 +1,67 @@
+use crate::authentication::UserId;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn change_email_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let email = get_email(*user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?
+        .unwrap_or_default();
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Change Email</title>
+        </head>
+        <body>
+            {msg_html}
+            <p>We use this address to tell you about changes to your account. Leave it empty to not be told.</p>
+            <form action="/admin/email" method="post">
+                <label>
+                    Email <input type="email" placeholder="Enter your email address" name="email" value="{email}">
+                </label>
+                <br>
+                <button type="submit">
+                    Change email
+                </button>
+            </form>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+        email = encode_attribute(&email),
+    )))
+}
+
+#[tracing::instrument(name = "Get user email", skip(pool))]
+async fn get_email(user_id: Uuid, pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the email of the user.")?;
+    Ok(row.email)
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::change_email_form;
+mod post;
+pub use post::change_email;
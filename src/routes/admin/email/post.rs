@@ -0,0 +1,58 @@
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// Set the address users are told about changes to their account at. An
+/// empty address removes it.
+pub async fn change_email(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match form.0.email.trim() {
+        "" => None,
+        email => match SubscriberEmail::parse(email.to_owned()) {
+            Ok(email) => Some(email),
+            Err(message) => {
+                FlashMessage::error(message).send();
+                return Ok(see_other("/admin/email"));
+            }
+        },
+    };
+    update_email(*user_id.into_inner(), email.as_ref(), &pool)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("Your email address has been changed.").send();
+    Ok(see_other("/admin/email"))
+}
+
+#[tracing::instrument(name = "Update user email", skip(pool))]
+async fn update_email(
+    user_id: Uuid,
+    email: Option<&SubscriberEmail>,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE users
+        SET email = $2
+        WHERE user_id = $1
+        "#,
+        user_id,
+        email.map(AsRef::as_ref),
+    );
+    pool.execute(query)
+        .await
+        .context("Failed to update the email of the user.")?;
+    Ok(())
+}
@@ -1,6 +1,18 @@
 mod dashboard;
 pub use dashboard::admin_dashboard;
+mod drafts;
+pub use drafts::*;
+mod dead_letters;
+pub use dead_letters::*;
+mod email;
+pub use email::*;
+mod newsletters;
+pub use newsletters::*;
 mod password;
 pub use password::*;
+mod scheduled_issues;
+pub use scheduled_issues::*;
+mod users;
+pub use users::*;
 mod logout;
-pub use logout::log_out;
\ No newline at end of file
+pub use logout::log_out;
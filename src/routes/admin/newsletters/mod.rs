@@ -0,0 +1,4 @@
+mod get;
+pub use get::publish_newsletter_form;
+mod post;
+pub use post::publish_newsletter_issue;
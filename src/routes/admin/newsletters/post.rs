@@ -0,0 +1,111 @@
+use crate::authentication::UserId;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::routes::{
+    check_templates, enqueue_delivery_tasks, get_list_ids, insert_newsletter_issue,
+    insert_newsletter_issue_lists, list_slugs_from_field, PublishError,
+};
+use crate::utils::{e400, e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    html_content: String,
+    text_content: String,
+    /// Comma-separated list slugs, our original newsletter if empty.
+    #[serde(default)]
+    lists: String,
+    /// Present when the "Track opens and clicks" box is ticked.
+    tracking: Option<String>,
+    idempotency_key: String,
+}
+
+fn success_message() -> FlashMessage {
+    FlashMessage::info("The newsletter issue has been published!")
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_newsletter_issue(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let FormData {
+        title,
+        html_content,
+        text_content,
+        lists,
+        tracking,
+        idempotency_key,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    if title.trim().is_empty() {
+        FlashMessage::error("The issue needs a title.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    if let Err(e) = check_templates(&text_content, &html_content) {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    let list_slugs = match list_slugs_from_field(&lists) {
+        Ok(list_slugs) => list_slugs,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            // The flash message is a cookie set outside of the handler, so it
+            // is not part of the saved response.
+            success_message().send();
+            return Ok(saved_response);
+        }
+    };
+    let list_ids = match get_list_ids(&mut transaction, &list_slugs).await {
+        Ok(list_ids) => list_ids,
+        Err(PublishError::ValidationError(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        *user_id,
+        &title,
+        &text_content,
+        &html_content,
+        Some(Utc::now()),
+        tracking.is_some(),
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    insert_newsletter_issue_lists(&mut transaction, issue_id, &list_ids)
+        .await
+        .context("Failed to store the lists of the newsletter issue")
+        .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    let response = see_other("/admin/newsletters");
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+    success_message().send();
+    Ok(response)
+}
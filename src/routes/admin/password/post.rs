@@ -1,11 +1,16 @@
 use crate::authentication::password::{validate_credentials, AuthError, Credentials};
 use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_templates::{PasswordChangedEmail, TransactionalEmail};
 use crate::routes::admin::dashboard::get_username;
 use crate::utils::{e500, see_other};
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
+use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
@@ -17,6 +22,7 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
     user_id: web::ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
@@ -29,7 +35,7 @@ pub async fn change_password(
     }
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
     let credentials = Credentials {
-        username,
+        username: username.clone(),
         password: form.0.current_password,
     };
     if let Err(e) = validate_credentials(credentials, &pool).await {
@@ -38,12 +44,52 @@ pub async fn change_password(
                 FlashMessage::error("The current password is incorrect.").send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
     crate::authentication::password::change_password(*user_id, form.0.new_password, &pool)
         .await
         .map_err(e500)?;
+    // The password is changed already: failing to tell is not worth an error page
+    if let Err(e) = notify_password_change(*user_id, &username, &pool, &email_client).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send a password changed email",
+        );
+    }
     FlashMessage::error("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
 }
+
+/// Let the user know about the change, in case someone else made it. Users
+/// without an email address are not told.
+#[tracing::instrument(name = "Notify password change", skip(pool, email_client))]
+async fn notify_password_change(
+    user_id: Uuid,
+    username: &str,
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<(), anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the email of the user.")?;
+    let Some(email) = row.email else {
+        return Ok(());
+    };
+    let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+    let message = PasswordChangedEmail { username }.message(email);
+    email_client
+        .send_message(&message)
+        .await
+        .context("Failed to send the email.")?;
+    Ok(())
+}
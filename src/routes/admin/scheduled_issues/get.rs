@@ -0,0 +1,109 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct ScheduledIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    lists: String,
+    send_at: DateTime<Utc>,
+}
+
+pub async fn scheduled_issues(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+    let mut rows_html = String::new();
+    for issue in get_scheduled_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{lists}</td>
+                <td>
+                    <form action="/admin/scheduled_issues/reschedule" method="post">
+                        <input type="hidden" name="newsletter_issue_id" value="{newsletter_issue_id}">
+                        <input type="text" name="send_at" value="{send_at}">
+                        <button type="submit">Reschedule</button>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/scheduled_issues/cancel" method="post">
+                        <input type="hidden" name="newsletter_issue_id" value="{newsletter_issue_id}">
+                        <button type="submit">Cancel</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            lists = htmlescape::encode_minimal(&issue.lists),
+            send_at = issue.send_at.to_rfc3339(),
+            newsletter_issue_id = issue.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Scheduled issues</title>
+        </head>
+        <body>
+            {msg_html}
+            <table>
+                <tr>
+                    <th>Issue</th>
+                    <th>Lists</th>
+                    <th>Send at (RFC 3339)</th>
+                    <th></th>
+                </tr>
+                {rows_html}
+            </table>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#
+        )))
+}
+
+#[tracing::instrument(name = "Get scheduled issues", skip(pool))]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT
+            s.newsletter_issue_id,
+            i.title,
+            string_agg(l.slug, ', ' ORDER BY l.slug) AS "lists!",
+            s.send_at
+        FROM scheduled_issues s
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        JOIN newsletter_issue_lists USING (newsletter_issue_id)
+        JOIN lists l USING (list_id)
+        GROUP BY s.newsletter_issue_id, i.title, s.send_at
+        ORDER BY s.send_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve scheduled issues.")?;
+    Ok(issues)
+}
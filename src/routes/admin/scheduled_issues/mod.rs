@@ -0,0 +1,4 @@
+mod get;
+pub use get::scheduled_issues;
+mod post;
+pub use post::{cancel_scheduled_issue, reschedule_issue};
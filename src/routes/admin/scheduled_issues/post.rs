@@ -0,0 +1,137 @@
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    newsletter_issue_id: Uuid,
+    send_at: String,
+}
+
+pub async fn reschedule_issue(
+    form: web::Form<RescheduleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let send_at = match DateTime::parse_from_rfc3339(&form.send_at) {
+        Ok(send_at) => send_at.with_timezone(&Utc),
+        Err(_) => {
+            FlashMessage::error(format!(
+                "{} is not a valid RFC 3339 timestamp.",
+                form.send_at
+            ))
+            .send();
+            return Ok(see_other("/admin/scheduled_issues"));
+        }
+    };
+    let rescheduled = update_send_at(&pool, form.newsletter_issue_id, send_at)
+        .await
+        .map_err(e500)?;
+    if rescheduled {
+        FlashMessage::info("The issue has been rescheduled.").send();
+    } else {
+        FlashMessage::error("The issue has already been sent or cancelled.").send();
+    }
+    Ok(see_other("/admin/scheduled_issues"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct CancelFormData {
+    newsletter_issue_id: Uuid,
+}
+
+pub async fn cancel_scheduled_issue(
+    form: web::Form<CancelFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let cancelled = delete_scheduled_issue(&pool, form.newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    if cancelled {
+        FlashMessage::info("The issue has been cancelled.").send();
+    } else {
+        FlashMessage::error("The issue has already been sent or cancelled.").send();
+    }
+    Ok(see_other("/admin/scheduled_issues"))
+}
+
+#[tracing::instrument(name = "Reschedule issue", skip(pool))]
+async fn update_send_at(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    send_at: DateTime<Utc>,
+) -> Result<bool, anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE scheduled_issues
+        SET send_at = $2
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        send_at
+    );
+    let n_updated_rows = pool
+        .execute(query)
+        .await
+        .context("Failed to reschedule the issue")?
+        .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Cancelled issues were never sent to anybody, so we drop them altogether.
+///
+/// The scheduler locks the row it releases: if it is releasing this issue
+/// right now, the delete waits for it and then finds nothing to cancel.
+#[tracing::instrument(name = "Cancel scheduled issue", skip(pool))]
+async fn delete_scheduled_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection")?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM scheduled_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    );
+    let n_deleted_rows = transaction.execute(query).await?.rows_affected();
+    if n_deleted_rows == 0 {
+        return Ok(false);
+    }
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issue_lists
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issue_translations
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    );
+    transaction.execute(query).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a scheduled issue")?;
+    Ok(true)
+}
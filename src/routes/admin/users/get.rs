@@ -0,0 +1,176 @@
+use crate::authentication::{Role, INVITATION_TTL_DAYS};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct User {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    disabled: bool,
+}
+
+struct Invitation {
+    username: String,
+    email: String,
+    role: String,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn users(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut users_html = String::new();
+    for user in get_users(&pool).await.map_err(e500)? {
+        let (status, toggle_action, toggle_label) = if user.disabled {
+            ("Disabled", "enable", "Enable")
+        } else {
+            ("Active", "disable", "Disable")
+        };
+        writeln!(
+            users_html,
+            r#"<tr>
+                <td>{username}</td>
+                <td>{email}</td>
+                <td>{role}</td>
+                <td>{status}</td>
+                <td>
+                    <form action="/admin/users/{toggle_action}" method="post">
+                        <input type="hidden" name="user_id" value="{user_id}">
+                        <button type="submit">{toggle_label}</button>
+                    </form>
+                    <form action="/admin/users/delete" method="post">
+                        <input type="hidden" name="user_id" value="{user_id}">
+                        <button type="submit">Delete</button>
+                    </form>
+                </td>
+            </tr>"#,
+            username = encode_minimal(&user.username),
+            email = encode_minimal(user.email.as_deref().unwrap_or("-")),
+            role = encode_minimal(&user.role),
+            user_id = user.user_id,
+        )
+        .unwrap();
+    }
+    let mut invitations_html = String::new();
+    for invitation in get_invitations(&pool).await.map_err(e500)? {
+        writeln!(
+            invitations_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&invitation.username),
+            encode_minimal(&invitation.email),
+            encode_minimal(&invitation.role),
+            invitation.created_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    let mut role_options_html = String::new();
+    for role in Role::ALL {
+        writeln!(
+            role_options_html,
+            r#"<option value="{}">{}</option>"#,
+            encode_attribute(role.as_str()),
+            encode_minimal(role.as_str()),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Users</title>
+        </head>
+        <body>
+            {msg_html}
+            <table>
+                <tr>
+                    <th>Username</th>
+                    <th>Email</th>
+                    <th>Role</th>
+                    <th>Status</th>
+                    <th></th>
+                </tr>
+                {users_html}
+            </table>
+            <p>Pending invitations:</p>
+            <table>
+                <tr>
+                    <th>Username</th>
+                    <th>Email</th>
+                    <th>Role</th>
+                    <th>Invited at</th>
+                </tr>
+                {invitations_html}
+            </table>
+            <p>Invite a user, who will choose their own password:</p>
+            <form action="/admin/users/invite" method="post">
+                <label>Username <input type="text" name="username"></label>
+                <label>Email <input type="email" name="email"></label>
+                <label>Role <select name="role">{role_options_html}</select></label>
+                <button type="submit">Invite</button>
+            </form>
+            <p>Create a user with a password of your choosing:</p>
+            <form action="/admin/users" method="post">
+                <label>Username <input type="text" name="username"></label>
+                <label>Email (optional) <input type="email" name="email"></label>
+                <label>Role <select name="role">{role_options_html}</select></label>
+                <label>Password <input type="password" name="password"></label>
+                <button type="submit">Create</button>
+            </form>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#
+        )))
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<User>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT user_id, username, email, role, disabled
+        FROM users
+        ORDER BY username
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the users.")?;
+    Ok(users)
+}
+
+#[tracing::instrument(name = "Get pending invitations", skip(pool))]
+async fn get_invitations(pool: &PgPool) -> Result<Vec<Invitation>, anyhow::Error> {
+    let invitations = sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT username, email, role, created_at
+        FROM user_invitations
+        WHERE created_at > now() - make_interval(days => $1)
+        ORDER BY created_at DESC
+        "#,
+        INVITATION_TTL_DAYS,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending invitations.")?;
+    Ok(invitations)
+}
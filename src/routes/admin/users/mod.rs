@@ -0,0 +1,4 @@
+mod get;
+pub use get::users;
+mod post;
+pub use post::{add_user, delete_user, disable_user, enable_user, invite_user};
@@ -0,0 +1,285 @@
+use crate::authentication::{create_user, username_is_taken, Role, UserId};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_templates::{InvitationEmail, TransactionalEmail};
+use crate::routes::admin::dashboard::get_username;
+use crate::routes::subscriptions::generate_subscription_token;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct AddUserFormData {
+    username: String,
+    /// Optional: users without an address are not told about changes to
+    /// their account.
+    #[serde(default)]
+    email: String,
+    role: String,
+    password: Secret<String>,
+}
+
+pub async fn add_user(
+    form: web::Form<AddUserFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let (username, role) = match parse_user(&form.username, &form.role, &pool).await {
+        Ok(user) => user,
+        Err(UserError::Invalid(message)) => {
+            FlashMessage::error(message).send();
+            return Ok(see_other("/admin/users"));
+        }
+        Err(UserError::Unexpected(e)) => return Err(e500(e)),
+    };
+    let email = match form.email.trim() {
+        "" => None,
+        email => match SubscriberEmail::parse(email.to_owned()) {
+            Ok(email) => Some(email),
+            Err(message) => {
+                FlashMessage::error(message).send();
+                return Ok(see_other("/admin/users"));
+            }
+        },
+    };
+    if form.password.expose_secret().is_empty() {
+        FlashMessage::error("The password cannot be empty.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection")
+        .map_err(e500)?;
+    create_user(
+        &mut transaction,
+        &username,
+        email.as_ref(),
+        role,
+        form.password,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a user")
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} can now log in.", username)).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct InviteUserFormData {
+    username: String,
+    email: String,
+    role: String,
+}
+
+pub async fn invite_user(
+    form: web::Form<InviteUserFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<Url>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let (username, role) = match parse_user(&form.username, &form.role, &pool).await {
+        Ok(user) => user,
+        Err(UserError::Invalid(message)) => {
+            FlashMessage::error(message).send();
+            return Ok(see_other("/admin/users"));
+        }
+        Err(UserError::Unexpected(e)) => return Err(e500(e)),
+    };
+    let email = match SubscriberEmail::parse(form.email.trim().to_owned()) {
+        Ok(email) => email,
+        Err(message) => {
+            FlashMessage::error(message).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let invitation_token = generate_subscription_token();
+    // Only keep the invitation, and the username it reserves, once it is sent
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection")
+        .map_err(e500)?;
+    store_invitation(&mut transaction, &invitation_token, &username, &email, role)
+        .await
+        .map_err(e500)?;
+    let inviter = get_username(*user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?;
+    let invitation_link = base_url
+        .join(&format!("invitations?token={invitation_token}"))
+        .context("Failed to construct the invitation link")
+        .map_err(e500)?;
+    let message = InvitationEmail {
+        username: &username,
+        inviter: &inviter,
+        role: role.as_str(),
+        invitation_link: invitation_link.as_str(),
+    }
+    .message(email);
+    email_client
+        .send_message(&message)
+        .await
+        .context("Failed to send the invitation")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an invitation")
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} has been invited.", username)).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct UserFormData {
+    user_id: Uuid,
+}
+
+pub async fn disable_user(
+    form: web::Form<UserFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if form.user_id == **user_id {
+        FlashMessage::error("You cannot disable your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    if set_disabled(&pool, form.user_id, true)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The user has been disabled.").send();
+    } else {
+        FlashMessage::error("The user could not be found.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+pub async fn enable_user(
+    form: web::Form<UserFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if set_disabled(&pool, form.user_id, false)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The user has been enabled.").send();
+    } else {
+        FlashMessage::error("The user could not be found.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+/// The issues of a deleted user are kept, without an author.
+pub async fn delete_user(
+    form: web::Form<UserFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if form.user_id == **user_id {
+        FlashMessage::error("You cannot delete your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    if remove_user(&pool, form.user_id).await.map_err(e500)? {
+        FlashMessage::info("The user has been deleted.").send();
+    } else {
+        FlashMessage::error("The user could not be found.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+enum UserError {
+    /// Fit for a flash message.
+    Invalid(String),
+    Unexpected(anyhow::Error),
+}
+
+/// Check the username is free and the role exists.
+async fn parse_user(
+    username: &str,
+    role: &str,
+    pool: &PgPool,
+) -> Result<(String, Role), UserError> {
+    let username = username.trim();
+    if username.is_empty() {
+        return Err(UserError::Invalid("The username cannot be empty.".into()));
+    }
+    let role = Role::parse(role).map_err(UserError::Invalid)?;
+    if username_is_taken(username, pool)
+        .await
+        .map_err(UserError::Unexpected)?
+    {
+        return Err(UserError::Invalid(format!(
+            "The username {} is already taken.",
+            username
+        )));
+    }
+    Ok((username.to_owned(), role))
+}
+
+#[tracing::instrument(name = "Store invitation", skip(transaction, invitation_token))]
+async fn store_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_token: &str,
+    username: &str,
+    email: &SubscriberEmail,
+    role: Role,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (invitation_token, username, email, role)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        invitation_token,
+        username,
+        email.as_ref(),
+        role.as_str(),
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to store the invitation")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Update user status", skip(pool))]
+async fn set_disabled(pool: &PgPool, user_id: Uuid, disabled: bool) -> Result<bool, anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE users
+        SET disabled = $2
+        WHERE user_id = $1
+        "#,
+        user_id,
+        disabled,
+    );
+    let n_updated_rows = pool
+        .execute(query)
+        .await
+        .context("Failed to update the status of the user")?
+        .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+#[tracing::instrument(name = "Delete user", skip(pool))]
+async fn remove_user(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let query = sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user_id);
+    let n_deleted_rows = pool
+        .execute(query)
+        .await
+        .context("Failed to delete the user")?
+        .rows_affected();
+    Ok(n_deleted_rows > 0)
+}
@@ -0,0 +1,179 @@
+use crate::authentication::{create_user, Role, INVITATION_TTL_DAYS};
+use crate::domain::SubscriberEmail;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct InvitationParameters {
+    token: String,
+}
+
+struct Invitation {
+    username: String,
+    email: String,
+    role: String,
+}
+
+/// Let invited users choose their password.
+pub async fn invitation_form(
+    parameters: web::Query<InvitationParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(invitation) = get_invitation(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(invalid_invitation());
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Set up your account</title>
+</head>
+<body>
+    {msg_html}
+    <p>Welcome {username}! Choose a password to set up your account.</p>
+    <form action="/invitations?token={token}" method="post">
+        <label>Password
+            <input type="password" placeholder="Enter password" name="password">
+        </label>
+        <br>
+        <label>Confirm password
+            <input type="password" placeholder="Type the password again" name="password_check">
+        </label>
+        <br>
+        <button type="submit">Set up my account</button>
+    </form>
+</body>
+</html>"#,
+            username = encode_minimal(&invitation.username),
+            token = encode_attribute(&parameters.token),
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct InvitationFormData {
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+/// Create the account of an invited user, who can log in right after.
+/// Each invitation can only be used once.
+#[tracing::instrument(name = "Accept invitation", skip(parameters, form, pool))]
+pub async fn accept_invitation(
+    parameters: web::Query<InvitationParameters>,
+    form: web::Form<InvitationFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form_location = format!(
+        "/invitations?token={}",
+        urlencoding::encode(&parameters.token)
+    );
+    if form.password.expose_secret().is_empty() {
+        FlashMessage::error("The password cannot be empty.").send();
+        return Ok(see_other(&form_location));
+    }
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&form_location));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection")
+        .map_err(e500)?;
+    let Some(invitation) = consume_invitation(&mut transaction, &parameters.token)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(invalid_invitation());
+    };
+    let role = Role::parse(&invitation.role)
+        .map_err(anyhow::Error::msg)
+        .map_err(e500)?;
+    let email = SubscriberEmail::parse(invitation.email)
+        .map_err(anyhow::Error::msg)
+        .map_err(e500)?;
+    create_user(
+        &mut transaction,
+        &invitation.username,
+        Some(&email),
+        role,
+        form.into_inner().password,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to accept an invitation")
+        .map_err(e500)?;
+    FlashMessage::info("Your account is ready: log in with your new password.").send();
+    Ok(see_other("/login"))
+}
+
+fn invalid_invitation() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .content_type(ContentType::html())
+        .body("<p>This invitation is not valid anymore. Ask for a new one.</p>")
+}
+
+/// The invitation the token was issued for, if it has neither expired nor
+/// been used already.
+#[tracing::instrument(name = "Get invitation", skip(pool, token))]
+async fn get_invitation(pool: &PgPool, token: &str) -> Result<Option<Invitation>, anyhow::Error> {
+    let invitation = sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT username, email, role
+        FROM user_invitations
+        WHERE invitation_token = $1 AND created_at > now() - make_interval(days => $2)
+        "#,
+        token,
+        INVITATION_TTL_DAYS,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the invitation.")?;
+    Ok(invitation)
+}
+
+/// Like `get_invitation`, deleting the invitation so that it cannot be used
+/// twice.
+#[tracing::instrument(name = "Consume invitation", skip(transaction, token))]
+async fn consume_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<Invitation>, anyhow::Error> {
+    let invitation = sqlx::query_as!(
+        Invitation,
+        r#"
+        DELETE FROM user_invitations
+        WHERE invitation_token = $1 AND created_at > now() - make_interval(days => $2)
+        RETURNING username, email, role
+        "#,
+        token,
+        INVITATION_TTL_DAYS,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to delete the invitation.")?;
+    Ok(invitation)
+}
@@ -0,0 +1,135 @@
+use crate::templating::Template;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+struct Issue {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+    author: Option<String>,
+}
+
+/// The public archive of every issue sent so far, newest first.
+pub async fn issues(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut items_html = String::new();
+    for issue in get_published_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            items_html,
+            r#"<li><a href="/issues/{}">{}</a> - {}</li>"#,
+            issue.newsletter_issue_id,
+            encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d"),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter archive</title>
+</head>
+<body>
+    <h1>Newsletter archive</h1>
+    <ul>
+        {items_html}
+    </ul>
+</body>
+</html>"#,
+        )))
+}
+
+/// The web version of an issue, linked from every email we send. Issues
+/// that are still scheduled are not public yet.
+pub async fn issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(issue) = get_published_issue(&pool, *newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let byline = match issue.author {
+        Some(author) => format!("by {} ", encode_minimal(&author)),
+        None => String::new(),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p><i>Published {byline}on {published_at}</i></p>
+    {html_content}
+    <p><a href="/issues">&lt;- All issues</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&issue.title),
+            published_at = issue.published_at.format("%Y-%m-%d"),
+            html_content = Template::render_web_html(&issue.html_content),
+        )))
+}
+
+#[tracing::instrument(name = "Get published issues", skip(pool))]
+async fn get_published_issues(pool: &PgPool) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id, title, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        ORDER BY published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the published issues.")?;
+    Ok(issues)
+}
+
+#[tracing::instrument(name = "Get published issue", skip(pool))]
+async fn get_published_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<Issue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT
+            i.title,
+            i.html_content,
+            i.published_at AS "published_at!",
+            u.username AS "author?"
+        FROM newsletter_issues i
+        LEFT JOIN users u ON u.user_id = i.author_user_id
+        WHERE i.newsletter_issue_id = $1 AND i.published_at IS NOT NULL
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the issue.")?;
+    Ok(issue)
+}
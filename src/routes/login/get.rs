@@ -9,6 +9,28 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
     }
     HttpResponse::Ok()
         .content_type(ContentType::html())
-        // find a way to include the error_html in the body
-        .body(include_str!("login.html"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+
+<body>
+    {error_html}
+    <form action="/login" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <label>Password
+            <input type="password" placeholder="Enter Password" name="password">
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+
+</html>"#
+        ))
 }
@@ -25,7 +25,7 @@ impl std::fmt::Debug for LoginError {
 impl ResponseError for LoginError {
     fn error_response(&self) -> HttpResponse {
         HttpResponse::build(self.status_code())
-            .insert_header((LOCATION, format!("/login")))
+            .insert_header((LOCATION, "/login"))
             .finish()
     }
     fn status_code(&self) -> StatusCode {
@@ -55,10 +55,10 @@ pub async fn login(
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             session.renew();
             session
                 .insert_user_id(user_id)
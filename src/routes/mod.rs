@@ -1,12 +1,24 @@
 mod health_check;
+mod invitations;
+mod issues;
 mod login;
 mod newsletter;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
+mod tracking;
+mod webhooks;
 pub use health_check::*;
+pub use invitations::*;
+pub use issues::*;
 pub use login::*;
 pub use newsletter::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
+pub use webhooks::*;
 mod admin;
 pub use admin::*;
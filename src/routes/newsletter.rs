@@ -1,7 +1,9 @@
-use crate::authentication::password::{validate_credentials, AuthError, Credentials};
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::authentication::password::{basic_authentication, validate_credentials, AuthError};
+use crate::authentication::{get_role, Role};
+use crate::domain::{ListSlug, Locale, SubscriberEmail};
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
 use crate::routes::subscriptions::error_chain_fmt;
+use crate::templating::Template;
 use actix_web::http::header::{self, HeaderMap, HeaderValue};
 use actix_web::http::StatusCode;
 use actix_web::web;
@@ -9,14 +11,18 @@ use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::ResponseError;
 use anyhow::Context;
-use base64::Engine;
-use secrecy::Secret;
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Only editors and owners can publish")]
+    Forbidden,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -30,6 +36,8 @@ impl std::fmt::Debug for PublishError {
 impl ResponseError for PublishError {
     fn error_response(&self) -> HttpResponse {
         match self {
+            PublishError::ValidationError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            PublishError::Forbidden => HttpResponse::new(StatusCode::FORBIDDEN),
             PublishError::UnexpectedError(_) => {
                 HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
             }
@@ -55,100 +63,366 @@ struct ConfirmedSubscriber {
 pub struct BodyData {
     title: String,
     content: Content,
+    /// The slugs of the lists to publish to, our original newsletter if empty.
+    #[serde(default)]
+    lists: Vec<String>,
+    /// When to release the issue to subscribers, right away if missing.
+    send_at: Option<DateTime<Utc>>,
+    /// Whether to record opens and link clicks of the issue.
+    #[serde(default)]
+    tracking: bool,
+    /// Versions of the issue for subscribers who read another language.
+    #[serde(default)]
+    translations: Vec<TranslationData>,
 }
 #[derive(serde::Deserialize)]
 pub struct Content {
     html: String,
     text: String,
 }
+#[derive(serde::Deserialize)]
+pub struct TranslationData {
+    locale: String,
+    title: String,
+    content: Content,
+}
+
+/// A version of an issue in another locale.
+pub struct Translation {
+    pub locale: Locale,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+fn translations(translations: Vec<TranslationData>) -> Result<Vec<Translation>, String> {
+    let mut parsed: Vec<Translation> = Vec::with_capacity(translations.len());
+    for translation in translations {
+        let locale = Locale::parse(translation.locale)?;
+        if parsed.iter().any(|t| t.locale == locale) {
+            return Err(format!(
+                "There are several translations to {}.",
+                locale.as_ref()
+            ));
+        }
+        check_templates(&translation.content.text, &translation.content.html)?;
+        parsed.push(Translation {
+            locale,
+            title: translation.title,
+            text_content: translation.content.text,
+            html_content: translation.content.html,
+        });
+    }
+    Ok(parsed)
+}
 
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
-    // The header value, if present, must be a valid UTF8 string
+fn idempotency_key(headers: &HeaderMap) -> Result<IdempotencyKey, anyhow::Error> {
     let header_value = headers
-        .get("Authorization")
-        .context("The 'Authorization' header was missing")?
+        .get("Idempotency-Key")
+        .context("The 'Idempotency-Key' header was missing")?
         .to_str()
-        .context("The 'Authorization' header was not a valid UTF8 string.")?;
-    let base64encoded_segment = header_value
-        .strip_prefix("Basic ")
-        .context("The authorization scheme was not 'Basic'.")?;
-    let decoded_bytes = base64::engine::general_purpose::STANDARD
-        .decode(base64encoded_segment)
-        .context("Failed to base64-decode 'Basic' credentials.")?;
-    let decoded_credentials = String::from_utf8(decoded_bytes)
-        .context("The decoded credential string is not valid UTF8.")?;
-    // Split into two segments, using ':' as delimiter
-    let mut credentials = decoded_credentials.splitn(2, ':');
-    let username = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
-        .to_string();
-    let password = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
-        .to_string();
-    Ok(Credentials {
-        username,
-        password: Secret::new(password),
-    })
+        .context("The 'Idempotency-Key' header was not a valid UTF8 string.")?;
+    header_value.to_owned().try_into()
 }
 
 #[tracing::instrument(
     name = "Publish newsletter",
-    skip(body, pool, email_client, request),
+    skip(body, pool, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty))
 ]
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     request: HttpRequest,
 ) -> Result<HttpResponse, PublishError> {
     let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
     let user_id = validate_credentials(credentials, &pool)
         .await
         .map_err(|e| match e {
-            AuthError::InvalidCredentials(e) => PublishError::AuthError(e.into()),
+            AuthError::InvalidCredentials(e) => PublishError::AuthError(e),
             e => PublishError::UnexpectedError(e.into()),
         })?;
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-    let subscribers = get_confirmed_subscribers(&pool).await?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })?;
-            }
-            Err(error) => {
-                tracing::warn!(error.cause_chain = ?error, "Skipping a confirmed subscriber due to invalid details")
-            }
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let role = get_role(user_id, &pool).await?;
+    if role < Some(Role::Editor) {
+        return Err(PublishError::Forbidden);
+    }
+    let idempotency_key = idempotency_key(request.headers())
+        .map_err(|e| PublishError::ValidationError(e.to_string()))?;
+    check_templates(&body.content.text, &body.content.html)
+        .map_err(PublishError::ValidationError)?;
+    let list_slugs = list_slugs(&body.lists).map_err(PublishError::ValidationError)?;
+    let body = body.into_inner();
+    let translations = translations(body.translations).map_err(PublishError::ValidationError)?;
+    let mut transaction = match try_processing(&pool, &idempotency_key, user_id).await? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+    let published_at = body.send_at.is_none().then(Utc::now);
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        user_id,
+        &body.title,
+        &body.content.text,
+        &body.content.html,
+        published_at,
+        body.tracking,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    let list_ids = get_list_ids(&mut transaction, &list_slugs).await?;
+    insert_newsletter_issue_lists(&mut transaction, issue_id, &list_ids)
+        .await
+        .context("Failed to store the lists of the newsletter issue")?;
+    insert_newsletter_issue_translations(&mut transaction, issue_id, &translations)
+        .await
+        .context("Failed to store the translations of the newsletter issue")?;
+    let response = match body.send_at {
+        Some(send_at) => {
+            schedule_issue(&mut transaction, issue_id, send_at)
+                .await
+                .context("Failed to schedule the newsletter issue")?;
+            HttpResponse::Accepted().finish()
+        }
+        None => {
+            enqueue_delivery_tasks(&mut transaction, issue_id).await?;
+            HttpResponse::Ok().finish()
+        }
+    };
+    let response = save_response(transaction, &idempotency_key, user_id, response).await?;
+    Ok(response)
+}
+
+/// Reject bodies whose placeholders could not be filled in for subscribers.
+pub fn check_templates(text_content: &str, html_content: &str) -> Result<(), String> {
+    Template::parse(text_content)?;
+    Template::parse(html_content)?;
+    Ok(())
+}
+
+pub fn list_slugs(lists: &[String]) -> Result<Vec<ListSlug>, String> {
+    if lists.is_empty() {
+        return Ok(vec![ListSlug::default()]);
+    }
+    let mut slugs = Vec::with_capacity(lists.len());
+    for list in lists {
+        let slug = ListSlug::parse(list.clone())?;
+        if !slugs.contains(&slug) {
+            slugs.push(slug);
         }
     }
-    Ok(HttpResponse::Ok().finish())
+    Ok(slugs)
+}
+
+/// Parse the comma-separated list slugs typed into the admin forms.
+pub fn list_slugs_from_field(field: &str) -> Result<Vec<ListSlug>, String> {
+    let lists: Vec<String> = field
+        .split(',')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect();
+    list_slugs(&lists)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_list_ids(
+    transaction: &mut Transaction<'_, Postgres>,
+    slugs: &[ListSlug],
+) -> Result<Vec<Uuid>, PublishError> {
+    let slugs: Vec<String> = slugs.iter().map(|s| s.as_ref().to_owned()).collect();
+    let rows = sqlx::query!(
+        r#"SELECT list_id, slug FROM lists WHERE slug = ANY($1)"#,
+        &slugs
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to look up the target lists")?;
+    let unknown: Vec<&str> = slugs
+        .iter()
+        .filter(|slug| !rows.iter().any(|r| &r.slug == *slug))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(PublishError::ValidationError(format!(
+            "Unknown lists: {}.",
+            unknown.join(", ")
+        )));
+    }
+    Ok(rows.into_iter().map(|r| r.list_id).collect())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    author_user_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    published_at: Option<DateTime<Utc>>,
+    tracking_enabled: bool,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            author_user_id,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            tracking_enabled
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        newsletter_issue_id,
+        author_user_id,
+        title,
+        text_content,
+        html_content,
+        published_at,
+        tracking_enabled
+    );
+    transaction.execute(query).await?;
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn insert_newsletter_issue_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, list_id
+        FROM UNNEST($2::uuid[]) AS list_id
+        "#,
+        newsletter_issue_id,
+        list_ids
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn insert_newsletter_issue_translations(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    translations: &[Translation],
+) -> Result<(), sqlx::Error> {
+    for translation in translations {
+        let query = sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue_translations (
+                newsletter_issue_id,
+                locale,
+                title,
+                text_content,
+                html_content
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            newsletter_issue_id,
+            translation.locale.as_ref(),
+            translation.title,
+            translation.text_content,
+            translation.html_content
+        );
+        transaction.execute(query).await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    send_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO scheduled_issues (newsletter_issue_id, send_at)
+        VALUES ($1, $2)
+        "#,
+        newsletter_issue_id,
+        send_at
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Queue one delivery task for each confirmed subscriber of the issue's lists.
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let subscribers = get_confirmed_subscribers(transaction, newsletter_issue_id)
+        .await?
+        .into_iter()
+        .filter_map(|subscriber| match subscriber {
+            Ok(subscriber) => Some(subscriber),
+            Err(error) => {
+                tracing::warn!(error.cause_chain = ?error, "Skipping a confirmed subscriber due to invalid details");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    insert_delivery_tasks(transaction, newsletter_issue_id, &subscribers)
+        .await
+        .context("Failed to enqueue delivery tasks")
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    subscribers: &[ConfirmedSubscriber],
+) -> Result<(), sqlx::Error> {
+    let emails: Vec<String> = subscribers
+        .iter()
+        .map(|s| s.email.as_ref().to_owned())
+        .collect();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, email
+        FROM UNNEST($2::text[]) AS email
+        "#,
+        newsletter_issue_id,
+        &emails
+    );
+    transaction.execute(query).await?;
+    Ok(())
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
+/// Subscribers of several target lists only get the issue once.
+#[tracing::instrument(name = "Get confirmed subscribers", skip(transaction))]
 async fn get_confirmed_subscribers(
-    pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
 ) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
     let rows = sqlx::query!(
-        r#" 
-        SELECT email 
-        FROM subscriptions 
-        WHERE status = 'confirmed'; 
+        r#"
+        SELECT DISTINCT s.email
+        FROM subscriptions s
+        JOIN list_subscriptions l ON l.subscriber_id = s.id
+        JOIN newsletter_issue_lists i ON i.list_id = l.list_id
+        WHERE
+            s.status = 'confirmed' AND
+            l.status = 'confirmed' AND
+            i.newsletter_issue_id = $1
         "#,
+        newsletter_issue_id
     )
-    .fetch_all(pool)
+    .fetch_all(&mut **transaction)
     .await?;
     let confirmed_subscribers = rows
         .into_iter()
@@ -1,6 +1,10 @@
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
+    domain::{
+        ListSlug, Locale, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus,
+    },
+    email_client::{EmailClient, EmailClientError},
+    email_templates::{ConfirmationEmail, TransactionalEmail},
+    i18n::Catalogue,
 };
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
@@ -28,22 +32,12 @@ impl std::fmt::Debug for SubscribeError {
         error_chain_fmt(&self, f)
     }
 }
-impl From<reqwest::Error> for SubscribeError {
-    fn from(e: reqwest::Error) -> Self {
-        Self::UnexpectedError(e.into())
-    }
-}
 
 impl From<String> for SubscribeError {
     fn from(e: String) -> Self {
         Self::ValidationError(e)
     }
 }
-impl From<url::ParseError> for SubscribeError {
-    fn from(e: url::ParseError) -> Self {
-        Self::ValidationError(e.to_string())
-    }
-}
 
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
@@ -72,7 +66,12 @@ impl TryFrom<FormData> for NewSubscriber {
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(value.name)?;
         let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+        let locale = value.locale.map(Locale::parse).transpose()?;
+        Ok(Self {
+            email,
+            name,
+            locale,
+        })
     }
 }
 
@@ -83,12 +82,134 @@ impl TryFrom<FormData> for NewSubscriber {
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Option<Uuid>,
     subscription_token: &str,
 ) -> Result<(), sqlx::Error> {
     let query = sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id) VALUES ($1, $2, $3)"#,
         subscription_token,
+        subscriber_id,
+        list_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get existing subscriber", skip(email, transaction))]
+pub async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1"#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(row.map(|r| r.id))
+}
+
+/// Whether the address hard-bounced or complained, as reported by our email
+/// provider: we must not email it anymore.
+#[tracing::instrument(name = "Check if the subscriber is suppressed", skip(transaction))]
+async fn is_suppressed(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT status IN ('bounced', 'complained') AS "suppressed!" FROM subscriptions WHERE id = $1"#,
         subscriber_id
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok(row.suppressed)
+}
+
+#[tracing::instrument(name = "Get list id", skip(transaction))]
+pub async fn get_list_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    slug: &ListSlug,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT list_id FROM lists WHERE slug = $1"#,
+        slug.as_ref()
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(row.map(|r| r.list_id))
+}
+
+#[tracing::instrument(name = "Get list subscription status", skip(transaction))]
+async fn get_list_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<Option<SubscriptionStatus>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT status FROM list_subscriptions WHERE subscriber_id = $1 AND list_id = $2"#,
+        subscriber_id,
+        list_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    row.map(|r| SubscriptionStatus::try_from(r.status).map_err(|e| anyhow::anyhow!(e)))
+        .transpose()
+}
+
+/// Tokens without a list confirm a new email address rather than a list
+/// subscription.
+#[tracing::instrument(name = "Delete subscription tokens", skip(transaction))]
+pub async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1 AND list_id IS NOT DISTINCT FROM $2"#,
+        subscriber_id,
+        list_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Adding a new list subscription", skip(transaction))]
+async fn insert_list_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+    VALUES ($1, $2, 'pending_confirmation', $3)
+    "#,
+        list_id,
+        subscriber_id,
+        Utc::now()
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Mark list subscription as pending confirmation",
+    skip(transaction)
+)]
+async fn mark_pending_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+    UPDATE list_subscriptions
+    SET status = 'pending_confirmation', subscribed_at = $3
+    WHERE subscriber_id = $1 AND list_id = $2
+    "#,
+        subscriber_id,
+        list_id,
+        Utc::now()
     );
     transaction.execute(query).await?;
     Ok(())
@@ -128,66 +249,159 @@ pub async fn subscribe(
     email_client: web::Data<EmailClient>,
     base_url: web::Data<Url>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into()?;
+    let list_slug = match form.list.clone() {
+        Some(list) => ListSlug::parse(list)?,
+        None => ListSlug::default(),
+    };
+    let new_subscriber: NewSubscriber = form.0.try_into()?;
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire Postgres connection")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let list_id = get_list_id(&mut transaction, &list_slug)
         .await
-        .context("Failed to insert new subscriber in the database")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .context("Failed to look up the mailing list")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!(
+                "{} is not a mailing list.",
+                list_slug.as_ref()
+            ))
+        })?;
+    let existing_subscriber = get_existing_subscriber(&mut transaction, &new_subscriber.email)
+        .await
+        .context("Failed to look up an existing subscriber")?;
+    if let Some(subscriber_id) = existing_subscriber {
+        if is_suppressed(&mut transaction, subscriber_id)
+            .await
+            .context("Failed to look up the status of an existing subscriber")?
+        {
+            // Emailing addresses that bounced or complained hurts our sender
+            // reputation. We answer as usual to avoid disclosing who is on
+            // our mailing list.
+            return Ok(HttpResponse::Ok().finish());
+        }
+    }
+    let subscriber_id = match existing_subscriber {
+        Some(subscriber_id) => subscriber_id,
+        None => insert_subscriber(&mut transaction, &new_subscriber)
+            .await
+            .context("Failed to insert new subscriber in the database")?,
+    };
+    let locale = update_locale(
+        &mut transaction,
+        subscriber_id,
+        new_subscriber.locale.as_ref(),
+    )
+    .await
+    .context("Failed to store the locale of the subscriber")?;
+    let status = get_list_subscription_status(&mut transaction, subscriber_id, list_id)
         .await
-        .context("Failed to store confirmation token for new subscriber")?;
+        .context("Failed to look up an existing list subscription")?;
+    match status {
+        // We answer as if it was a brand new subscription to avoid
+        // disclosing who is on our mailing list.
+        Some(SubscriptionStatus::Confirmed) => return Ok(HttpResponse::Ok().finish()),
+        // Subscribing again before confirming replaces the previous token
+        Some(SubscriptionStatus::PendingConfirmation) => {
+            delete_tokens(&mut transaction, subscriber_id, Some(list_id))
+                .await
+                .context("Failed to delete stale confirmation tokens")?
+        }
+        // Coming back after unsubscribing requires confirming again
+        Some(SubscriptionStatus::Unsubscribed) => {
+            mark_pending_confirmation(&mut transaction, subscriber_id, list_id)
+                .await
+                .context("Failed to reset the status of a former subscriber")?
+        }
+        None => insert_list_subscription(&mut transaction, subscriber_id, list_id)
+            .await
+            .context("Failed to insert new list subscription in the database")?,
+    }
+    let subscription_token = generate_subscription_token();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        Some(list_id),
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store confirmation token for new subscriber")?;
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber")?;
     send_confirmation_email(
         &email_client,
-        new_subscriber,
+        new_subscriber.email,
+        &locale,
         &base_url,
         &subscription_token,
     )
-    .await?;
+    .await
+    .context("Failed to send a confirmation email")?;
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Keep the locale the subscriber asked for, if any, and return the one they
+/// will get: new subscribers who did not say get the default.
+#[tracing::instrument(name = "Update subscriber locale", skip(transaction))]
+async fn update_locale(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    locale: Option<&Locale>,
+) -> Result<Locale, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET locale = COALESCE($2, locale)
+        WHERE id = $1
+        RETURNING locale
+        "#,
+        subscriber_id,
+        locale.map(AsRef::as_ref)
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok(Locale::parse(row.locale).unwrap_or_default())
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(email_client, recipient)
 )]
-async fn send_confirmation_email(
+pub async fn send_confirmation_email(
     email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+    recipient: SubscriberEmail,
+    locale: &Locale,
     base_url: &Url,
     subscription_token: &str,
-) -> Result<(), SubscribeError> {
+) -> Result<(), EmailClientError> {
     let confirmation_link = Url::join(
         base_url,
         &format!("subscriptions/confirm?subscription_token={subscription_token}"),
     )
     .expect("Failed to construct confirmation link");
-    let confirmation_link = confirmation_link.as_str();
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!("Welcome to our newsletter!<br /> Click <a href=\"{}\">here</a> to confirm your subscription.", confirmation_link);
-    email_client
-        .send_email(&new_subscriber.email, "Welcome!", &plain_body, &html_body)
-        .await
+    let message = ConfirmationEmail {
+        confirmation_link: confirmation_link.as_str(),
+        catalogue: Catalogue::for_locale(locale),
+    }
+    .message(recipient);
+    email_client.send_message(&message).await?;
+    Ok(())
 }
 
 #[derive(Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// The slug of the list to join, our original newsletter if missing.
+    list: Option<String>,
+    /// The language to write to the subscriber in, e.g. `fr` or `pt-BR`.
+    locale: Option<String>,
 }
 
 /// Generate a random 25-characters-long case-sensitive subscription token.
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
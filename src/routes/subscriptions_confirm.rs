@@ -1,5 +1,18 @@
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use crate::configuration::WelcomeEmailSettings;
+use crate::domain::{Locale, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::email_templates::{TransactionalEmail, WelcomeEmail};
+use crate::i18n::Catalogue;
+use crate::routes::subscriptions::delete_tokens;
+use crate::startup::SubscriptionTokenTtl;
+use crate::utils::e500;
+use actix_web::http::header::{self, ContentType};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use sqlx::{Executor, PgPool};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -7,54 +20,209 @@ pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(pool, parameters))]
-pub async fn confirm(pool: web::Data<PgPool>, parameters: web::Query<Parameters>) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(
+        pool,
+        parameters,
+        token_ttl,
+        request,
+        email_client,
+        base_url,
+        welcome_email
+    )
+)]
+pub async fn confirm(
+    pool: web::Data<PgPool>,
+    parameters: web::Query<Parameters>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+    request: HttpRequest,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<Url>,
+    welcome_email: web::Data<WelcomeEmailSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = get_subscription_token(&pool, &parameters.subscription_token)
+        .await
+        .map_err(e500)?;
+    let Some(token) = token else {
+        // We cannot tell who clicked: answer in the language of their browser
+        let accept_language = request
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default();
+        let catalogue = Catalogue::negotiate(accept_language);
+        return Ok(confirmation_page(
+            StatusCode::UNAUTHORIZED,
+            catalogue,
+            catalogue.confirmation_link_invalid,
+        ));
     };
-    match id {
-        None => HttpResponse::Unauthorized().finish(),
-        Some(id) => {
-            if confirm_subscriber(&pool, id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
+    let catalogue = Catalogue::for_locale(&Locale::parse(token.locale.clone()).unwrap_or_default());
+    if token.is_expired(token_ttl.0) {
+        return Ok(confirmation_page(
+            StatusCode::GONE,
+            catalogue,
+            catalogue.confirmation_link_expired,
+        ));
+    }
+    confirm_subscriber(&pool, &token).await.map_err(e500)?;
+    // Subscribers joining another list are already past their welcome, and
+    // addresses that bounced or complained must not be emailed again
+    if welcome_email.enabled && token.status == "pending_confirmation" {
+        if let Err(e) = send_welcome_email(&email_client, &token, &base_url, catalogue).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a welcome email",
+            );
         }
     }
+    Ok(confirmation_page(
+        StatusCode::OK,
+        catalogue,
+        catalogue.confirmed,
+    ))
 }
 
+fn confirmation_page(
+    status: StatusCode,
+    catalogue: &'static Catalogue,
+    message: &str,
+) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .insert_header((header::CONTENT_LANGUAGE, catalogue.language))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{language}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <p>{message}</p>
+</body>
+</html>"#,
+            language = catalogue.language,
+            title = catalogue.confirmation_title,
+        ))
+}
+
+#[tracing::instrument(
+    name = "Send a welcome email to a new subscriber",
+    skip(email_client, token, base_url, catalogue)
+)]
+async fn send_welcome_email(
+    email_client: &EmailClient,
+    token: &StoredToken,
+    base_url: &Url,
+    catalogue: &'static Catalogue,
+) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(token.email.clone()).map_err(anyhow::Error::msg)?;
+    let issues_link = base_url
+        .join("issues")
+        .context("Failed to construct the issues link")?;
+    let message = WelcomeEmail {
+        name: &token.name,
+        issues_link: issues_link.as_str(),
+        catalogue,
+    }
+    .message(recipient);
+    email_client
+        .send_message(&message)
+        .await
+        .context("Failed to send the email.")?;
+    Ok(())
+}
+
+/// A confirmation token, with the subscriber it was issued to.
+struct StoredToken {
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+    created_at: DateTime<Utc>,
+    email: String,
+    name: String,
+    locale: String,
+    status: String,
+}
+
+impl StoredToken {
+    fn is_expired(&self, ttl: std::time::Duration) -> bool {
+        match chrono::Duration::from_std(ttl) {
+            Ok(ttl) => self.created_at + ttl < Utc::now(),
+            // A TTL too large to be represented never expires
+            Err(_) => false,
+        }
+    }
+}
+
+/// Mark the subscriber as confirmed, together with the list subscription the
+/// token was issued for, and burn the matching tokens so that each
+/// confirmation link can only be used once.
 #[tracing::instrument(name = "Update subscriber status to confirmed", skip(pool, token))]
-async fn confirm_subscriber(pool: &PgPool, token: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+async fn confirm_subscriber(pool: &PgPool, token: &StoredToken) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection")?;
+    let query = sqlx::query!(
         r#"
         UPDATE subscriptions
         SET status = 'confirmed'
-        WHERE id = $1
+        WHERE id = $1 AND status NOT IN ('bounced', 'complained')
         "#,
-        token
-    )
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
+        token.subscriber_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to update the subscriber status")?;
+    if let Some(list_id) = token.list_id {
+        let query = sqlx::query!(
+            r#"
+            UPDATE list_subscriptions
+            SET status = 'confirmed'
+            WHERE subscriber_id = $1 AND list_id = $2
+            "#,
+            token.subscriber_id,
+            list_id
+        );
+        transaction
+            .execute(query)
+            .await
+            .context("Failed to update the list subscription status")?;
+    }
+    delete_tokens(&mut transaction, token.subscriber_id, token.list_id)
+        .await
+        .context("Failed to delete used confirmation tokens")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber")
+        .map_err(|e| {
+            tracing::error!("Failed to confirm subscriber: {:?}", e);
+            e
+        })?;
     Ok(())
 }
 
-#[tracing::instrument(name = "Get subscriber ID from token", skip(pool, token))]
-async fn get_subscriber_id_from_token(
+#[tracing::instrument(name = "Get subscription token", skip(pool, token))]
+async fn get_subscription_token(
     pool: &PgPool,
     token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
+) -> Result<Option<StoredToken>, sqlx::Error> {
+    let result = sqlx::query_as!(
+        StoredToken,
         r#"
-        SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1
+        SELECT t.subscriber_id, t.list_id, t.created_at, s.email, s.name, s.locale, s.status
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1
         "#,
         token
     )
     .fetch_optional(pool)
     .await?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result)
 }
@@ -0,0 +1,130 @@
+use super::PreferencesParameters;
+use crate::subscriber_links::SubscriberLinks;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct Subscriber {
+    email: String,
+    name: String,
+}
+
+struct List {
+    list_id: Uuid,
+    name: String,
+    subscribed: bool,
+}
+
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(subscriber_id) = links.verify_preferences_token(&parameters.token) else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    let Some(subscriber) = get_subscriber(&pool, subscriber_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in get_lists(&pool, subscriber_id).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<label>
+                    <input type="checkbox" name="lists" value="{list_id}" {checked}> {name}
+                </label>
+                <br>"#,
+            list_id = list.list_id,
+            checked = if list.subscribed { "checked" } else { "" },
+            name = encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Your preferences</title>
+        </head>
+        <body>
+            {msg_html}
+            <form action="{action}" method="post">
+                <label>
+                    Name <input type="text" name="name" value="{name}">
+                </label>
+                <br>
+                <label>
+                    Email <input type="email" name="email" value="{email}">
+                </label>
+                <br>
+                <fieldset>
+                    <legend>Lists</legend>
+                    {lists_html}
+                </fieldset>
+                <button type="submit">
+                    Save preferences
+                </button>
+            </form>
+        </body>
+        </html>
+        "#,
+            action = encode_attribute(&parameters.page()),
+            name = encode_attribute(&subscriber.name),
+            email = encode_attribute(&subscriber.email),
+        )))
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"SELECT email, name FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    Ok(subscriber)
+}
+
+/// Lists awaiting confirmation count as subscribed: unticking them is how
+/// the subscriber backs out.
+#[tracing::instrument(name = "Get lists", skip(pool))]
+async fn get_lists(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<List>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        List,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            COALESCE(s.status <> 'unsubscribed', false) AS "subscribed!"
+        FROM lists l
+        LEFT JOIN list_subscriptions s
+            ON s.list_id = l.list_id AND s.subscriber_id = $1
+        ORDER BY l.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve lists.")?;
+    Ok(lists)
+}
@@ -0,0 +1,17 @@
+mod get;
+pub use get::preferences_form;
+mod post;
+pub use post::update_preferences;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+impl PreferencesParameters {
+    fn page(&self) -> String {
+        // The token went through `verify_preferences_token`, so it only
+        // contains URL-safe characters.
+        format!("/subscriptions/preferences?token={}", self.token)
+    }
+}
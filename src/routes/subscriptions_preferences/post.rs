@@ -0,0 +1,246 @@
+use super::PreferencesParameters;
+use crate::domain::{Locale, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::routes::{
+    delete_tokens, generate_subscription_token, get_existing_subscriber, send_confirmation_email,
+    store_token,
+};
+use crate::subscriber_links::SubscriberLinks;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use reqwest::Url;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+struct Preferences {
+    name: SubscriberName,
+    email: SubscriberEmail,
+    list_ids: Vec<Uuid>,
+}
+
+impl TryFrom<Vec<(String, String)>> for Preferences {
+    type Error = String;
+    /// Checkboxes are submitted as one `lists` field per checked box, so we
+    /// get the raw pairs instead of a struct with a single value per field.
+    fn try_from(form: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut name = None;
+        let mut email = None;
+        let mut list_ids = Vec::new();
+        for (key, value) in form {
+            match key.as_str() {
+                "name" => name = Some(value),
+                "email" => email = Some(value),
+                "lists" => list_ids.push(
+                    Uuid::parse_str(&value)
+                        .map_err(|_| format!("{} is not a valid list.", value))?,
+                ),
+                _ => {}
+            }
+        }
+        let name = SubscriberName::parse(name.ok_or("The name is missing.")?)?;
+        let email = SubscriberEmail::parse(email.ok_or("The email is missing.")?)?;
+        Ok(Self {
+            name,
+            email,
+            list_ids,
+        })
+    }
+}
+
+#[tracing::instrument(
+    name = "Update subscriber preferences",
+    skip(parameters, form, pool, email_client, base_url, links)
+)]
+pub async fn update_preferences(
+    parameters: web::Query<PreferencesParameters>,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<Url>,
+    links: web::Data<SubscriberLinks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(subscriber_id) = links.verify_preferences_token(&parameters.token) else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    let preferences = match Preferences::try_from(form.into_inner()) {
+        Ok(preferences) => preferences,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&parameters.page()));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection")
+        .map_err(e500)?;
+    let Some((current_email, locale)) = get_email_and_locale(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    let email_changed = current_email != preferences.email.as_ref();
+    if email_changed {
+        let existing_subscriber = get_existing_subscriber(&mut transaction, &preferences.email)
+            .await
+            .map_err(e500)?;
+        if existing_subscriber.is_some() {
+            FlashMessage::error("This email address is already in use.").send();
+            return Ok(see_other(&parameters.page()));
+        }
+    }
+    update_name(&mut transaction, subscriber_id, &preferences.name)
+        .await
+        .context("Failed to update the subscriber name")
+        .map_err(e500)?;
+    replace_lists(&mut transaction, subscriber_id, &preferences.list_ids)
+        .await
+        .context("Failed to update the subscriber lists")
+        .map_err(e500)?;
+    let subscription_token = if email_changed {
+        let subscription_token = generate_subscription_token();
+        change_email(
+            &mut transaction,
+            subscriber_id,
+            &preferences.email,
+            &subscription_token,
+        )
+        .await
+        .map_err(e500)?;
+        Some(subscription_token)
+    } else {
+        None
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber preferences")
+        .map_err(e500)?;
+    match subscription_token {
+        Some(subscription_token) => {
+            send_confirmation_email(
+                &email_client,
+                preferences.email,
+                &locale,
+                &base_url,
+                &subscription_token,
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::info(
+                "Your preferences have been updated. Check your inbox to confirm your new email address.",
+            )
+            .send();
+        }
+        None => FlashMessage::info("Your preferences have been updated.").send(),
+    }
+    Ok(see_other(&parameters.page()))
+}
+
+#[tracing::instrument(name = "Get subscriber email and locale", skip(transaction))]
+async fn get_email_and_locale(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<(String, Locale)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT email, locale FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to retrieve the subscriber email.")?;
+    Ok(row.map(|r| (r.email, Locale::parse(r.locale).unwrap_or_default())))
+}
+
+#[tracing::instrument(name = "Update subscriber name", skip(transaction, name))]
+async fn update_name(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2 WHERE id = $1"#,
+        subscriber_id,
+        name.as_ref()
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Unknown list ids are ignored rather than rejected: a list may have been
+/// removed while the subscriber had the page open.
+///
+/// The preferences link was mailed to the subscriber, so joining a list from
+/// here needs no further confirmation; lists still awaiting confirmation are
+/// left as they are.
+#[tracing::instrument(name = "Replace subscriber lists", skip(transaction))]
+async fn replace_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE list_subscriptions
+        SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        list_ids
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        SELECT list_id, $1, 'confirmed', $3
+        FROM lists
+        WHERE list_id = ANY($2)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = 'confirmed', subscribed_at = EXCLUDED.subscribed_at
+        WHERE list_subscriptions.status = 'unsubscribed'
+        "#,
+        subscriber_id,
+        list_ids,
+        Utc::now()
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// A new email address must be confirmed before we send issues to it, but
+/// the subscriber keeps their lists.
+#[tracing::instrument(
+    name = "Change subscriber email",
+    skip(transaction, email, subscription_token)
+)]
+async fn change_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &SubscriberEmail,
+    subscription_token: &str,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET email = $2, status = 'pending_confirmation'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        email.as_ref()
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to update the subscriber email")?;
+    delete_tokens(transaction, subscriber_id, None)
+        .await
+        .context("Failed to delete stale confirmation tokens")?;
+    store_token(transaction, subscriber_id, None, subscription_token)
+        .await
+        .context("Failed to store confirmation token for the new email")?;
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use crate::domain::Locale;
+use crate::i18n::Catalogue;
+use crate::subscriber_links::SubscriberLinks;
+use crate::utils::e500;
+use actix_web::http::header::{self, ContentType};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use htmlescape::encode_attribute;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+/// Ask the subscriber to confirm before unsubscribing: link scanners and
+/// prefetchers follow GET requests, so they must not change anything.
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(subscriber_id) = links.verify_unsubscribe_token(&parameters.token) else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    let catalogue = get_catalogue(&pool, subscriber_id).await.map_err(e500)?;
+    let token = encode_attribute(&parameters.token);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header((header::CONTENT_LANGUAGE, catalogue.language))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{language}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <p>{question}</p>
+    <form action="/subscriptions/unsubscribe?token={token}" method="post">
+        <button type="submit">{button}</button>
+    </form>
+</body>
+</html>"#,
+            language = catalogue.language,
+            title = catalogue.unsubscribe_title,
+            question = catalogue.unsubscribe_question,
+            button = catalogue.unsubscribe_button,
+        )))
+}
+
+/// Handles both the form above and RFC 8058 one-click requests, which POST
+/// `List-Unsubscribe=One-Click` to the link found in the email headers.
+/// Subscribers leave every list they joined.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool, links))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(subscriber_id) = links.verify_unsubscribe_token(&parameters.token) else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    mark_unsubscribed(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let catalogue = get_catalogue(&pool, subscriber_id).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header((header::CONTENT_LANGUAGE, catalogue.language))
+        .body(format!("<p>{}</p>", catalogue.unsubscribed)))
+}
+
+/// The catalogue for the subscriber's locale. Subscribers deleted since the
+/// link was sent get English.
+#[tracing::instrument(name = "Get subscriber catalogue", skip(pool))]
+async fn get_catalogue(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<&'static Catalogue, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT locale FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber locale.")?;
+    let locale = row
+        .and_then(|r| Locale::parse(r.locale).ok())
+        .unwrap_or_default();
+    Ok(Catalogue::for_locale(&locale))
+}
+
+#[tracing::instrument(name = "Update list subscriptions to unsubscribed", skip(pool))]
+async fn mark_unsubscribed(pool: &PgPool, subscriber_id: Uuid) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE list_subscriptions
+        SET status = 'unsubscribed'
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    );
+    pool.execute(query)
+        .await
+        .context("Failed to update the list subscriptions status")?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use crate::subscriber_links::SubscriberLinks;
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+/// The smallest transparent GIF there is.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Record that a subscriber opened a tracked issue, when their mail client
+/// downloads the pixel embedded in it.
+#[tracing::instrument(name = "Track an open", skip(token, pool, links))]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+) -> HttpResponse {
+    let Ok((newsletter_issue_id, subscriber_id)) = links.verify_open_token(&token) else {
+        return HttpResponse::NotFound().finish();
+    };
+    // Failing to count an open is no reason to show a broken image
+    if let Err(e) = record_event(&pool, newsletter_issue_id, subscriber_id, "open", None).await {
+        tracing::error!(error.cause_chain = ?e, "Failed to record an open");
+    }
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL)
+}
+
+/// Record a click on a link of a tracked issue and send the subscriber on to
+/// the link's target.
+#[tracing::instrument(name = "Track a click", skip(token, pool, links))]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+) -> HttpResponse {
+    let Ok((newsletter_issue_id, subscriber_id, url)) = links.verify_click_token(&token) else {
+        return HttpResponse::NotFound().finish();
+    };
+    // Nor is it a reason to keep the subscriber from their destination
+    if let Err(e) = record_event(
+        &pool,
+        newsletter_issue_id,
+        subscriber_id,
+        "click",
+        Some(url.as_str()),
+    )
+    .await
+    {
+        tracing::error!(error.cause_chain = ?e, "Failed to record a click");
+    }
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url.as_str()))
+        .finish()
+}
+
+#[tracing::instrument(name = "Record a tracking event", skip(pool))]
+async fn record_event(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    kind: &str,
+    url: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    // Subscribers who have since been deleted are not counted
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_tracking_events (
+            event_id,
+            newsletter_issue_id,
+            subscriber_id,
+            kind,
+            url,
+            occurred_at
+        )
+        SELECT $1, $2, $3, $4, $5, now()
+        WHERE EXISTS (SELECT 1 FROM subscriptions WHERE id = $3)
+        "#,
+        Uuid::new_v4(),
+        newsletter_issue_id,
+        subscriber_id,
+        kind,
+        url
+    );
+    pool.execute(query)
+        .await
+        .context("Failed to store the tracking event")?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use crate::authentication::password::basic_authentication;
+use crate::configuration::EmailWebhookSettings;
+use crate::routes::subscriptions::error_chain_fmt;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use sqlx::{Executor, PgPool};
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("The event is not valid")]
+    InvalidEvent(#[source] serde_json::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            WebhookError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            WebhookError::InvalidEvent(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            WebhookError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+        }
+    }
+}
+
+/// The events Postmark posts to us, told apart by their `RecordType`.
+/// Deliveries, opens and the like are acknowledged and ignored.
+#[derive(serde::Deserialize)]
+#[serde(tag = "RecordType")]
+pub enum EmailEvent {
+    Bounce {
+        #[serde(rename = "Type")]
+        bounce_type: String,
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    SpamComplaint {
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Receive bounce and spam complaint events from our email provider.
+///
+/// Addresses that hard-bounced or complained stop receiving issues: they are
+/// no longer `confirmed`, which is what the delivery queries look for.
+/// The body is only parsed once the caller is authenticated.
+#[tracing::instrument(name = "Receive an email event", skip_all)]
+pub async fn email_webhook(
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    settings: web::Data<EmailWebhookSettings>,
+    request: HttpRequest,
+) -> Result<HttpResponse, WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    let username_matches = credentials.username == settings.username;
+    let password_matches = constant_time_eq(
+        credentials.password.expose_secret(),
+        settings.password.expose_secret(),
+    );
+    if !(username_matches && password_matches) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials."
+        )));
+    }
+    let event: EmailEvent = serde_json::from_slice(&body).map_err(WebhookError::InvalidEvent)?;
+    match event {
+        // Soft bounces (full mailbox, greylisting...) may well go through next time
+        EmailEvent::Bounce { bounce_type, email } if bounce_type == "HardBounce" => {
+            mark_subscriber(&pool, &email, "bounced").await?
+        }
+        EmailEvent::SpamComplaint { email } => mark_subscriber(&pool, &email, "complained").await?,
+        EmailEvent::Bounce { .. } | EmailEvent::Other => {}
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Update subscriber status", skip(pool, email))]
+async fn mark_subscriber(pool: &PgPool, email: &str, status: &str) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2
+        WHERE email = $1
+        "#,
+        email,
+        status
+    );
+    pool.execute(query)
+        .await
+        .context("Failed to update the subscriber status")?;
+    Ok(())
+}
+
+/// Compare the HMACs of both values, keyed by the expected one: unlike `==`,
+/// the time taken tells nothing about how much of the guess was right.
+fn constant_time_eq(provided: &str, expected: &str) -> bool {
+    let mac = |value: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(expected.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(value.as_bytes());
+        mac
+    };
+    let expected_tag = mac(expected).finalize().into_bytes();
+    mac(provided).verify_slice(&expected_tag).is_ok()
+}
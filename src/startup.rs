@@ -5,15 +5,22 @@ use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
 use std::net::TcpListener;
+use std::time::Duration;
 
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{reject_anonymous_users, reject_unauthorised_users};
+use crate::configuration::{DatabaseSettings, EmailClientSettings, Settings, WelcomeEmailSettings};
 use crate::domain::home;
-use crate::email_client::EmailClient;
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, log_out, login,
-    login_form, publish_newsletter, subscribe,
+    accept_invitation, add_user, admin_dashboard, cancel_scheduled_issue, change_email,
+    change_email_form, change_password, change_password_form, confirm, create_draft, dead_letters,
+    delete_user, disable_user, draft_form, drafts, email_webhook, enable_user, health_check,
+    invitation_form, invite_user, issue, issues, log_out, login, login_form, preferences_form,
+    preview_draft, publish_draft, publish_newsletter, publish_newsletter_form,
+    publish_newsletter_issue, replay_dead_letter, reschedule_issue, scheduled_issues,
+    send_test_draft, subscribe, track_click, track_open, unsubscribe, unsubscribe_form,
+    update_draft, update_preferences, users,
 };
+use crate::subscriber_links::SubscriberLinks;
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpServer};
 use reqwest::Url;
@@ -33,20 +40,6 @@ pub struct Application {
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration
-                .email_client
-                .base_url()
-                .expect("Failed to parse base url"),
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
-        );
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
@@ -54,12 +47,15 @@ impl Application {
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
         let base_url = Url::parse(&configuration.application.base_url).expect("Invalid base URL");
+        let subscription_token_ttl = configuration.application.subscription_token_ttl();
         let server = run(
             listener,
             connection_pool,
-            email_client,
+            configuration.email_client,
             base_url,
+            subscription_token_ttl,
             configuration.application.hmac_secret,
+            configuration.welcome_email,
             configuration.redis_uri,
         )
         .await?;
@@ -73,16 +69,25 @@ impl Application {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client_settings: EmailClientSettings,
     base_url: Url,
+    subscription_token_ttl: Duration,
     hmac_secret: Secret<String>,
+    welcome_email_settings: WelcomeEmailSettings,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
-    let email_client = web::Data::new(email_client);
+    let email_webhook_settings = web::Data::new(email_client_settings.webhook.clone());
+    let email_client = web::Data::new(email_client_settings.client());
+    let welcome_email_settings = web::Data::new(welcome_email_settings);
     let db_pool = web::Data::new(db_pool);
+    let subscriber_links = web::Data::new(SubscriberLinks::new(
+        base_url.clone(),
+        HmacSecret(hmac_secret.clone()),
+    ));
     let base_url = web::Data::new(base_url);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
@@ -95,21 +100,75 @@ pub async fn run(
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
             .route("/newsletter", web::post().to(publish_newsletter))
+            .route("/webhooks/email", web::post().to(email_webhook))
+            .route("/t/o/{token}", web::get().to(track_open))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/issues", web::get().to(issues))
+            .route("/issues/{newsletter_issue_id}", web::get().to(issue))
+            .route("/invitations", web::get().to(invitation_form))
+            .route("/invitations", web::post().to(accept_invitation))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/", web::get().to(home))
             .service(
                 web::scope("/admin")
+                    // The last middleware registered runs first
+                    .wrap(from_fn(reject_unauthorised_users))
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out)),
+                    .route("/email", web::get().to(change_email_form))
+                    .route("/email", web::post().to(change_email))
+                    .route("/logout", web::post().to(log_out))
+                    .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .route("/newsletters", web::post().to(publish_newsletter_issue))
+                    .route("/dead_letters", web::get().to(dead_letters))
+                    .route("/dead_letters/replay", web::post().to(replay_dead_letter))
+                    .route("/scheduled_issues", web::get().to(scheduled_issues))
+                    .route(
+                        "/scheduled_issues/reschedule",
+                        web::post().to(reschedule_issue),
+                    )
+                    .route(
+                        "/scheduled_issues/cancel",
+                        web::post().to(cancel_scheduled_issue),
+                    )
+                    .route("/drafts", web::get().to(drafts))
+                    .route("/drafts", web::post().to(create_draft))
+                    .route("/drafts/{draft_id}", web::get().to(draft_form))
+                    .route("/drafts/{draft_id}", web::post().to(update_draft))
+                    .route("/drafts/{draft_id}/preview", web::get().to(preview_draft))
+                    .route("/drafts/{draft_id}/test", web::post().to(send_test_draft))
+                    .route("/drafts/{draft_id}/publish", web::post().to(publish_draft))
+                    .route("/users", web::get().to(users))
+                    .route("/users", web::post().to(add_user))
+                    .route("/users/invite", web::post().to(invite_user))
+                    .route("/users/disable", web::post().to(disable_user))
+                    .route("/users/enable", web::post().to(enable_user))
+                    .route("/users/delete", web::post().to(delete_user)),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(email_webhook_settings.clone())
+            .app_data(welcome_email_settings.clone())
             .app_data(base_url.clone())
+            .app_data(subscriber_links.clone())
+            .app_data(web::Data::new(SubscriptionTokenTtl(subscription_token_ttl)))
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
             .wrap(SessionMiddleware::new(
                 redis_store.clone(),
@@ -122,3 +181,7 @@ pub async fn run(
 }
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
+
+/// How long a subscription confirmation token remains valid.
+#[derive(Clone, Copy)]
+pub struct SubscriptionTokenTtl(pub Duration);
@@ -0,0 +1,235 @@
+use crate::startup::HmacSecret;
+use anyhow::Context;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+
+const UNSUBSCRIBE: &str = "unsubscribe";
+const PREFERENCES: &str = "preferences";
+const OPEN: &str = "open";
+const CLICK: &str = "click";
+
+/// Builds and verifies the per-subscriber links we embed in outgoing emails.
+///
+/// Each link carries a token made of a payload and its HMAC tag, so that
+/// subscribers can act on their own subscription without logging in.
+#[derive(Clone)]
+pub struct SubscriberLinks {
+    base_url: Url,
+    hmac_secret: HmacSecret,
+}
+
+impl SubscriberLinks {
+    pub fn new(base_url: Url, hmac_secret: HmacSecret) -> Self {
+        Self {
+            base_url,
+            hmac_secret,
+        }
+    }
+
+    pub fn unsubscribe(&self, subscriber_id: Uuid) -> Url {
+        let token = self.sign(UNSUBSCRIBE, &subscriber_id.to_string());
+        self.link("subscriptions/unsubscribe", &token)
+    }
+
+    pub fn verify_unsubscribe_token(&self, token: &str) -> Result<Uuid, anyhow::Error> {
+        let payload = self.verify(UNSUBSCRIBE, token)?;
+        Uuid::parse_str(&payload).context("The token payload is not a subscriber id.")
+    }
+
+    pub fn preferences(&self, subscriber_id: Uuid) -> Url {
+        let token = self.sign(PREFERENCES, &subscriber_id.to_string());
+        self.link("subscriptions/preferences", &token)
+    }
+
+    pub fn verify_preferences_token(&self, token: &str) -> Result<Uuid, anyhow::Error> {
+        let payload = self.verify(PREFERENCES, token)?;
+        Uuid::parse_str(&payload).context("The token payload is not a subscriber id.")
+    }
+
+    /// The public web version of an issue: unlike the links above it is the
+    /// same for every subscriber and carries no token.
+    pub fn web_version(&self, newsletter_issue_id: Uuid) -> Url {
+        self.base_url
+            .join(&format!("issues/{}", newsletter_issue_id))
+            .expect("Failed to construct the web version link")
+    }
+
+    /// A 1x1 image whose download tells us the subscriber opened the issue.
+    pub fn open_pixel(&self, newsletter_issue_id: Uuid, subscriber_id: Uuid) -> Url {
+        let token = self.sign(OPEN, &format!("{}:{}", newsletter_issue_id, subscriber_id));
+        self.tracking_link("t/o", &token)
+    }
+
+    /// Returns the issue and the subscriber the pixel was issued for.
+    pub fn verify_open_token(&self, token: &str) -> Result<(Uuid, Uuid), anyhow::Error> {
+        let payload = self.verify(OPEN, token)?;
+        let (newsletter_issue_id, subscriber_id) = payload
+            .split_once(':')
+            .context("The token payload is not an issue and a subscriber.")?;
+        Ok((
+            Uuid::parse_str(newsletter_issue_id).context("Invalid issue id.")?,
+            Uuid::parse_str(subscriber_id).context("Invalid subscriber id.")?,
+        ))
+    }
+
+    /// A redirect to `url` that records the click on the way. The target is
+    /// signed: the link cannot be turned into an open redirect.
+    pub fn click(&self, newsletter_issue_id: Uuid, subscriber_id: Uuid, url: &str) -> Url {
+        let token = self.sign(
+            CLICK,
+            &format!("{}:{}:{}", newsletter_issue_id, subscriber_id, url),
+        );
+        self.tracking_link("t/c", &token)
+    }
+
+    /// Returns the issue, the subscriber and the target of the link.
+    pub fn verify_click_token(&self, token: &str) -> Result<(Uuid, Uuid, Url), anyhow::Error> {
+        let payload = self.verify(CLICK, token)?;
+        let mut parts = payload.splitn(3, ':');
+        let (Some(newsletter_issue_id), Some(subscriber_id), Some(url)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("The token payload is not an issue, a subscriber and a URL.");
+        };
+        Ok((
+            Uuid::parse_str(newsletter_issue_id).context("Invalid issue id.")?,
+            Uuid::parse_str(subscriber_id).context("Invalid subscriber id.")?,
+            Url::parse(url).context("Invalid link target.")?,
+        ))
+    }
+
+    /// Tracking tokens go in the path: some mail clients drop query strings
+    /// from image URLs.
+    fn tracking_link(&self, path: &str, token: &str) -> Url {
+        self.base_url
+            .join(&format!("{}/{}", path, token))
+            .expect("Failed to construct tracking link")
+    }
+
+    fn link(&self, path: &str, token: &str) -> Url {
+        let mut url = self
+            .base_url
+            .join(path)
+            .expect("Failed to construct subscriber link");
+        url.query_pairs_mut().append_pair("token", token);
+        url
+    }
+
+    fn mac(&self, purpose: &str, payload: &str) -> Hmac<sha2::Sha256> {
+        let mut mac =
+            Hmac::<sha2::Sha256>::new_from_slice(self.hmac_secret.0.expose_secret().as_bytes())
+                .expect("HMAC can take a key of any size");
+        // The purpose is part of the signed message: a token issued for one
+        // kind of link cannot be replayed against another.
+        mac.update(purpose.as_bytes());
+        mac.update(b":");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    fn sign(&self, purpose: &str, payload: &str) -> String {
+        let tag = self.mac(purpose, payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(tag)
+        )
+    }
+
+    fn verify(&self, purpose: &str, token: &str) -> Result<String, anyhow::Error> {
+        let (payload, tag) = token
+            .split_once('.')
+            .context("The token is not made of a payload and a tag.")?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .context("Failed to base64-decode the token payload.")?;
+        let payload = String::from_utf8(payload).context("The token payload is not valid UTF8.")?;
+        let tag = URL_SAFE_NO_PAD
+            .decode(tag)
+            .context("Failed to base64-decode the token tag.")?;
+        self.mac(purpose, &payload)
+            .verify_slice(&tag)
+            .context("The token tag does not match its payload.")?;
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberLinks;
+    use crate::startup::HmacSecret;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use claims::{assert_err, assert_ok_eq};
+    use reqwest::Url;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn links(secret: &str) -> SubscriberLinks {
+        SubscriberLinks::new(
+            Url::parse("http://127.0.0.1").unwrap(),
+            HmacSecret(Secret::new(secret.to_string())),
+        )
+    }
+
+    fn token(url: &Url) -> String {
+        url.query_pairs()
+            .find(|(k, _)| k == "token")
+            .unwrap()
+            .1
+            .into_owned()
+    }
+
+    #[test]
+    fn a_signed_unsubscribe_link_is_verified() {
+        let links = links("secret");
+        let subscriber_id = Uuid::new_v4();
+        let url = links.unsubscribe(subscriber_id);
+        assert_eq!(url.path(), "/subscriptions/unsubscribe");
+        assert_ok_eq!(links.verify_unsubscribe_token(&token(&url)), subscriber_id);
+    }
+
+    #[test]
+    fn a_link_signed_with_another_secret_is_rejected() {
+        let url = links("another-secret").unsubscribe(Uuid::new_v4());
+        assert_err!(links("secret").verify_unsubscribe_token(&token(&url)));
+    }
+
+    #[test]
+    fn an_unsubscribe_token_cannot_be_used_for_preferences() {
+        let links = links("secret");
+        let url = links.unsubscribe(Uuid::new_v4());
+        assert_err!(links.verify_preferences_token(&token(&url)));
+    }
+
+    #[test]
+    fn a_click_link_carries_its_signed_target() {
+        let links = links("secret");
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let url = links.click(issue_id, subscriber_id, "https://example.com/a?b=c:d");
+        let token = url.path().strip_prefix("/t/c/").unwrap();
+        let (verified_issue_id, verified_subscriber_id, target) =
+            links.verify_click_token(token).unwrap();
+        assert_eq!(verified_issue_id, issue_id);
+        assert_eq!(verified_subscriber_id, subscriber_id);
+        assert_eq!(target.as_str(), "https://example.com/a?b=c:d");
+        assert_err!(links.verify_open_token(token));
+    }
+
+    #[test]
+    fn a_tampered_payload_is_rejected() {
+        let links = links("secret");
+        let token = token(&links.unsubscribe(Uuid::new_v4()));
+        let (_, tag) = token.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(Uuid::new_v4().to_string()),
+            tag
+        );
+        assert_err!(links.verify_unsubscribe_token(&forged));
+    }
+}
@@ -0,0 +1,174 @@
+use htmlescape::encode_attribute;
+
+/// The values a newsletter body can refer to with `{{variable}}` placeholders.
+pub struct Recipient<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub preferences_url: &'a str,
+}
+
+impl Recipient<'static> {
+    /// Stands in for subscribers on pages that are not addressed to anyone,
+    /// such as the web version of an issue: its links lead nowhere.
+    pub const ANONYMOUS: Recipient<'static> = Recipient {
+        name: "reader",
+        email: "",
+        unsubscribe_url: "#",
+        preferences_url: "#",
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+    Name,
+    Email,
+    UnsubscribeUrl,
+    PreferencesUrl,
+}
+
+impl Variable {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "name" => Ok(Self::Name),
+            "email" => Ok(Self::Email),
+            "unsubscribe_url" => Ok(Self::UnsubscribeUrl),
+            "preferences_url" => Ok(Self::PreferencesUrl),
+            _ => Err(format!("{{{{{}}}}} is not a known template variable.", s)),
+        }
+    }
+
+    fn value<'a>(&self, recipient: &Recipient<'a>) -> &'a str {
+        match self {
+            Self::Name => recipient.name,
+            Self::Email => recipient.email,
+            Self::UnsubscribeUrl => recipient.unsubscribe_url,
+            Self::PreferencesUrl => recipient.preferences_url,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+/// A newsletter body with per-subscriber placeholders.
+///
+/// Templates are parsed when an issue is submitted, so that a typo in a
+/// placeholder is reported to the author instead of reaching subscribers.
+#[derive(Debug)]
+pub struct Template(Vec<Segment>);
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Template, String> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_owned()));
+            }
+            let after_start = &rest[start + 2..];
+            let end = after_start
+                .find("}}")
+                .ok_or("A template placeholder is missing its closing braces.")?;
+            let variable = Variable::parse(after_start[..end].trim())?;
+            segments.push(Segment::Variable(variable));
+            rest = &after_start[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_owned()));
+        }
+        Ok(Self(segments))
+    }
+
+    /// Substitute the placeholders of an HTML body. Values are escaped so
+    /// that they are safe both in text and inside attributes.
+    pub fn render_html(&self, recipient: &Recipient) -> String {
+        self.render(recipient, encode_attribute)
+    }
+
+    /// Like `render_html`, for a body shown on the web rather than mailed.
+    /// Bodies that are not valid templates, such as unfinished drafts, are
+    /// shown as they are.
+    pub fn render_web_html(html: &str) -> String {
+        match Self::parse(html) {
+            Ok(template) => template.render_html(&Recipient::ANONYMOUS),
+            Err(_) => html.to_owned(),
+        }
+    }
+
+    pub fn render_text(&self, recipient: &Recipient) -> String {
+        self.render(recipient, str::to_owned)
+    }
+
+    fn render(&self, recipient: &Recipient, encode: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                Segment::Variable(variable) => {
+                    rendered.push_str(&encode(variable.value(recipient)))
+                }
+            }
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Recipient, Template};
+    use claims::{assert_err, assert_ok};
+
+    fn recipient() -> Recipient<'static> {
+        Recipient {
+            name: "Ursula <Le Guin>",
+            email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=a&b",
+            preferences_url: "https://example.com/preferences?token=c",
+        }
+    }
+
+    #[test]
+    fn text_without_placeholders_is_left_untouched() {
+        let template = Template::parse("Hello, world!").unwrap();
+        assert_eq!(template.render_text(&recipient()), "Hello, world!");
+    }
+
+    #[test]
+    fn placeholders_are_substituted_in_text() {
+        let template =
+            Template::parse("Hi {{name}} ({{ email }}), leave at {{unsubscribe_url}}").unwrap();
+        assert_eq!(
+            template.render_text(&recipient()),
+            "Hi Ursula <Le Guin> (ursula@example.com), leave at https://example.com/unsubscribe?token=a&b"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let rendered = Template::parse(r#"<p>Hi {{name}}</p><a href="{{unsubscribe_url}}">"#)
+            .unwrap()
+            .render_html(&recipient());
+        assert!(!rendered.contains("<Le Guin>"));
+        assert!(!rendered.contains("a&b"));
+        assert!(rendered.starts_with("<p>Hi Ursula"));
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_err!(Template::parse("Hi {{nmae}}"));
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_err!(Template::parse("Hi {{name"));
+    }
+
+    #[test]
+    fn a_single_brace_is_not_a_placeholder() {
+        assert_ok!(Template::parse("fn main() { }"));
+    }
+}
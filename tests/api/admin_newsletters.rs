@@ -0,0 +1,144 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+fn newsletter_form_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_newsletter_form() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_publish_newsletter().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_publish_a_newsletter() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_publish_newsletter(&newsletter_form_body()).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn newsletters_published_from_the_form_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act - Part 1 - Submit the form
+    let response = app.post_publish_newsletter(&newsletter_form_body()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn submitting_the_form_twice_publishes_the_issue_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = newsletter_form_body();
+    // Act - Part 1 - Submit the form
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // Act - Part 2 - Submit it again
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email once
+}
+
+#[tokio::test]
+async fn invalid_newsletter_forms_are_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let mut missing_title = newsletter_form_body();
+    missing_title["title"] = "".into();
+    let mut unknown_list = newsletter_form_body();
+    unknown_list["lists"] = "nope".into();
+    let mut unknown_variable = newsletter_form_body();
+    unknown_variable["text_content"] = "Dear {{nmae}}".into();
+    let test_cases = vec![
+        (missing_title, "<p><i>The issue needs a title.</i></p>"),
+        (
+            unknown_variable,
+            "<p><i>{{nmae}} is not a known template variable.</i></p>",
+        ),
+        (unknown_list, "<p><i>Unknown lists: nope.</i></p>"),
+    ];
+    for (body, error_message) in test_cases {
+        // Act
+        let response = app.post_publish_newsletter(&body).await;
+        // Assert
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        let html_page = app.get_publish_newsletter_html().await;
+        assert!(html_page.contains(error_message));
+    }
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn error_messages_escape_what_was_submitted() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let mut body = newsletter_form_body();
+    body["lists"] = "<script>".into();
+    // Act
+    app.post_publish_newsletter(&body).await;
+    // Assert
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>&lt;script&gt; is not a valid list slug.</i></p>"));
+    assert!(!html_page.contains("<script>"));
+}
@@ -0,0 +1,326 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn add_user_body(username: &str, role: &str) -> serde_json::Value {
+    serde_json::json!({
+        "username": username,
+        "email": "",
+        "role": role,
+        "password": "a-long-enough-password",
+    })
+}
+
+async fn get_user(app: &TestApp, username: &str) -> Option<(String, bool)> {
+    sqlx::query!(
+        "SELECT role, disabled FROM users WHERE username = $1",
+        username
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| (r.role, r.disabled))
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = app.create_user("editor").await;
+    editor.login(&app).await;
+    // Act
+    let page = app.get_users().await;
+    let response = app
+        .post_users("", &add_user_body("intruder", "owner"))
+        .await;
+    // Assert
+    assert_eq!(page.status().as_u16(), 403);
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(get_user(&app, "intruder").await.is_none());
+    assert!(!app
+        .get_admin_dashboard_html()
+        .await
+        .contains("/admin/users"));
+}
+
+#[tokio::test]
+async fn viewers_can_look_around_but_not_change_anything() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = app.create_user("viewer").await;
+    viewer.login(&app).await;
+    // Act
+    let dashboard = app.get_admin_dashboard().await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    // Assert
+    assert_eq!(dashboard.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn owners_can_create_users_who_can_then_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act - Part 1 - Create the user
+    let response = app
+        .post_users("", &add_user_body("octavia", "editor"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>octavia can now log in.</i></p>"));
+    // Act - Part 2 - Log in as the new user
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "octavia",
+            "password": "a-long-enough-password",
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(get_user(&app, "octavia").await.unwrap().0, "editor");
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let username = app.test_user.username.clone();
+    // Act
+    app.post_users("", &add_user_body(&username, "viewer"))
+        .await;
+    // Assert
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains(&format!("The username {} is already taken.", username)));
+    assert_eq!(get_user(&app, &username).await.unwrap().0, "owner");
+}
+
+#[tokio::test]
+async fn flash_messages_escape_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    app.post_users("", &add_user_body("<b>octavia</b>", "viewer"))
+        .await;
+    // Assert
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>&lt;b&gt;octavia&lt;/b&gt; can now log in.</i></p>"));
+}
+
+#[tokio::test]
+async fn disabled_users_are_logged_out_and_cannot_log_in_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = app.create_user("editor").await;
+    app.test_user.login(&app).await;
+    // Act - Part 1 - Disable the user
+    let response = app
+        .post_users("disable", &serde_json::json!({ "user_id": editor.user_id }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(get_user(&app, &editor.username).await.unwrap().1);
+    // Act - Part 2 - Try to log in as the disabled user
+    let response = editor.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+    // Act - Part 3 - Enable the user again
+    app.test_user.login(&app).await;
+    app.post_users("enable", &serde_json::json!({ "user_id": editor.user_id }))
+        .await;
+    let response = editor.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    // Act - Part 4 - Disable the user while logged in
+    sqlx::query!(
+        "UPDATE users SET disabled = true WHERE user_id = $1",
+        editor.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let response = app.get_admin_dashboard().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_cannot_disable_or_delete_themselves() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let body = serde_json::json!({ "user_id": app.test_user.user_id });
+    // Act
+    app.post_users("disable", &body).await;
+    let disable_page = app.get_users_html().await;
+    app.post_users("delete", &body).await;
+    let delete_page = app.get_users_html().await;
+    // Assert
+    assert!(disable_page.contains("You cannot disable your own account."));
+    assert!(delete_page.contains("You cannot delete your own account."));
+    assert_eq!(
+        get_user(&app, &app.test_user.username).await,
+        Some(("owner".into(), false))
+    );
+}
+
+#[tokio::test]
+async fn deleting_a_user_keeps_their_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = app.create_user("editor").await;
+    let response = app
+        .api_client
+        .post(format!("{}/newsletter", &app.address))
+        .basic_auth(&editor.username, Some(&editor.password))
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({ "title": "Newsletter title", "content": { "text": "Body", "html": "<p>Body</p>" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.test_user.login(&app).await;
+    // Act
+    let response = app
+        .post_users("delete", &serde_json::json!({ "user_id": editor.user_id }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(get_user(&app, &editor.username).await.is_none());
+    let issue = sqlx::query!("SELECT title, author_user_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Newsletter title");
+    assert_eq!(issue.author_user_id, None);
+}
+
+#[tokio::test]
+async fn invited_users_choose_their_own_password() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act - Part 1 - Invite
+    let response = app
+        .post_users(
+            "invite",
+            &serde_json::json!({
+                "username": "ursula",
+                "email": "ursula_le_guin@gmail.com",
+                "role": "viewer",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("ursula has been invited."));
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    let text_body = body["TextBody"].as_str().unwrap();
+    let link = linkify::LinkFinder::new()
+        .links(text_body)
+        .find(|l| *l.kind() == linkify::LinkKind::Url)
+        .unwrap();
+    let mut invitation_link = reqwest::Url::parse(link.as_str()).unwrap();
+    assert_eq!(invitation_link.host_str().unwrap(), "127.0.0.1");
+    invitation_link.set_port(Some(app.port)).unwrap();
+    // Act - Part 2 - Open the invitation
+    let response = app
+        .api_client
+        .get(invitation_link.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Welcome ursula!"));
+    // Act - Part 3 - Choose a password
+    let password = serde_json::json!({
+        "password": "a-long-enough-password",
+        "password_check": "a-long-enough-password",
+    });
+    let response = app
+        .api_client
+        .post(invitation_link.clone())
+        .form(&password)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    // Act - Part 4 - Log in
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": "a-long-enough-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(get_user(&app, "ursula").await.unwrap().0, "viewer");
+    // Act - Part 5 - Use the invitation again
+    let response = app
+        .api_client
+        .post(invitation_link)
+        .form(&password)
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn failing_to_send_an_invitation_does_not_reserve_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let invitation = serde_json::json!({
+        "username": "ursula",
+        "email": "ursula_le_guin@gmail.com",
+        "role": "viewer",
+    });
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act - Part 1 - The email cannot be sent
+    let response = app.post_users("invite", &invitation).await;
+    assert_eq!(response.status().as_u16(), 500);
+    let n_invitations = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM user_invitations"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_invitations, 0);
+    // Act - Part 2 - Try again
+    let response = app.post_users("invite", &invitation).await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("ursula has been invited."));
+}
@@ -0,0 +1,69 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_your_email() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let get_response = app.get_change_email().await;
+    let post_response = app
+        .post_change_email(&serde_json::json!({ "email": "admin@example.com" }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&get_response, "/login");
+    assert_is_redirect_to(&post_response, "/login");
+}
+
+#[tokio::test]
+async fn viewers_can_set_and_remove_their_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = app.create_user("viewer").await;
+    viewer.login(&app).await;
+    // Act - Part 1 - Set the email
+    let response = app
+        .post_change_email(&serde_json::json!({ "email": "viewer@example.com" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/email");
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_change_email_html().await;
+    assert!(html_page.contains("<p><i>Your email address has been changed.</i></p>"));
+    let value = format!(
+        r#"value="{}""#,
+        htmlescape::encode_attribute("viewer@example.com")
+    );
+    assert!(html_page.contains(&value));
+    // Act - Part 3 - Remove it
+    app.post_change_email(&serde_json::json!({ "email": "" }))
+        .await;
+    // Assert
+    let saved = sqlx::query!("SELECT email FROM users WHERE user_id = $1", viewer.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, None);
+}
+
+#[tokio::test]
+async fn invalid_emails_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app
+        .post_change_email(&serde_json::json!({ "email": "definitely-not-an-email" }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/email");
+    let html_page = app.get_change_email_html().await;
+    assert!(html_page
+        .contains("<p><i>definitely-not-an-email is not a valid subscriber email.</i></p>"));
+    let saved = sqlx::query!(
+        "SELECT email FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.email, None);
+}
@@ -0,0 +1,148 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn dead_letter_attempts(app: &TestApp) -> Vec<i16> {
+    sqlx::query!("SELECT n_attempts FROM issue_delivery_dead_letters")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch dead letters.")
+        .into_iter()
+        .map(|r| r.n_attempts)
+        .collect()
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried_until_the_attempt_limit() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(app.issue_delivery_settings.max_attempts as u64)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(
+        dead_letter_attempts(&app).await,
+        vec![app.issue_delivery_settings.max_attempts]
+    );
+}
+
+#[tokio::test]
+async fn a_delivery_succeeding_on_retry_is_not_dead_lettered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert!(dead_letter_attempts(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn permanent_failures_are_dead_lettered_without_retrying() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(dead_letter_attempts(&app).await, vec![1]);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_failed_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/admin/dead_letters", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn dead_letters_can_be_inspected_and_replayed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    drop(_mock_guard);
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+    // Act - Part 1 - Inspect the failed deliveries
+    let html_page = app.get_dead_letters_html().await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    // Act - Part 2 - Replay the failed delivery
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let response = app
+        .post_replay_dead_letter(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+            "subscriber_email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dead_letters");
+    let html_page = app.get_dead_letters_html().await;
+    assert!(html_page
+        .contains("<p><i>The delivery to ursula_le_guin@gmail.com has been queued again.</i></p>"));
+    // Act - Part 3 - Deliver the replayed email
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert!(dead_letter_attempts(&app).await.is_empty());
+}
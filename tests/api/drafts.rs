@@ -0,0 +1,238 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+/// Create a draft through the admin area and fill it in.
+async fn create_draft(app: &TestApp) -> Uuid {
+    let response = app
+        .post_drafts(&serde_json::json!({ "title": "Newsletter title" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let draft_id = sqlx::query!("SELECT draft_id FROM newsletter_drafts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .draft_id;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+    let response = app
+        .post_draft(
+            draft_id,
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+    draft_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let list_response = app
+        .api_client
+        .get(format!("{}/admin/drafts", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let create_response = app
+        .post_drafts(&serde_json::json!({ "title": "Newsletter title" }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&list_response, "/login");
+    assert_is_redirect_to(&create_response, "/login");
+}
+
+#[tokio::test]
+async fn drafts_can_be_created_edited_and_listed() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    // Act - Part 1 - Create and edit a draft
+    let draft_id = create_draft(&app).await;
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains("Newsletter body as plain text"));
+    assert!(html_page.contains("&lt;p&gt;Newsletter body as HTML&lt;/p&gt;"));
+    // Act - Part 2 - List the drafts
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/drafts/{}">Newsletter title</a>"#,
+        draft_id
+    )));
+}
+
+#[tokio::test]
+async fn unknown_drafts_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    // Act
+    let response = app.get_draft(Uuid::new_v4()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_preview_renders_the_html_content() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let draft_id = create_draft(&app).await;
+    // Act
+    let response = app.get_draft_preview(draft_id).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "<p>Newsletter body as HTML</p>"
+    );
+}
+
+#[tokio::test]
+async fn the_preview_fills_in_the_placeholders() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let draft_id = create_draft(&app).await;
+    app.post_draft(
+        draft_id,
+        &serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Hi {{name}}!</p>",
+        }),
+    )
+    .await;
+    // Act
+    let response = app.get_draft_preview(draft_id).await;
+    // Assert
+    assert_eq!(response.text().await.unwrap(), "<p>Hi reader!</p>");
+}
+
+#[tokio::test]
+async fn test_sends_only_reach_the_chosen_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login(&app).await;
+    let draft_id = create_draft(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app
+        .post_send_test_draft(
+            draft_id,
+            &serde_json::json!({ "email": "editor@example.com" }),
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>A test email has been sent to editor@example.com.</i></p>"));
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "editor@example.com");
+    assert_eq!(body["Subject"], "[TEST] Newsletter title");
+    // The draft is still there and no issue was published
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn test_sends_reject_invalid_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let draft_id = create_draft(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app
+        .post_send_test_draft(draft_id, &serde_json::json!({ "email": "not-an-email" }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>not-an-email is not a valid subscriber email.</i></p>"));
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_to_every_subscriber_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login(&app).await;
+    let draft_id = create_draft(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act - Part 1 - Publish the draft
+    let response = app
+        .post_publish_draft(draft_id, &serde_json::json!({ "lists": "newsletter" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page
+        .contains("<p><i>The issue &quot;Newsletter title&quot; has been published.</i></p>"));
+    assert!(!html_page.contains(&draft_id.to_string()));
+    // Act - Part 2 - Publishing twice is reported
+    app.post_publish_draft(draft_id, &serde_json::json!({ "lists": "newsletter" }))
+        .await;
+    let html_page = app.get_drafts_html().await;
+    assert!(
+        html_page.contains("<p><i>The draft does not exist or has already been published.</i></p>")
+    );
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email once
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_keeps_the_draft() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let draft_id = create_draft(&app).await;
+    // Act
+    let response = app
+        .post_publish_draft(draft_id, &serde_json::json!({ "lists": "nope" }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>Unknown lists: nope.</i></p>"));
+}
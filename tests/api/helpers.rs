@@ -1,12 +1,20 @@
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use startup::get_connection_pool;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 use zero2prod::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{
+        get_configuration, DatabaseSettings, EmailWebhookSettings, IssueDeliverySettings, Settings,
+    },
+    email_client::EmailClient,
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    issue_scheduler::try_release_issue,
     startup::{self, Application},
+    subscriber_links::SubscriberLinks,
     telemetry::{get_subscriber, init_subscriber},
 };
 
@@ -23,20 +31,29 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub email_client: EmailClient,
+    pub issue_delivery_settings: IssueDeliverySettings,
+    pub subscriber_links: SubscriberLinks,
+    pub email_webhook_settings: EmailWebhookSettings,
 }
 
 pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: String,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role("owner")
+    }
+    pub fn generate_with_role(role: &str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role: role.into(),
         }
     }
     async fn store(&self, pool: &PgPool) {
@@ -46,21 +63,83 @@ impl TestUser {
             .unwrap()
             .to_string();
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password_hash, role) VALUES ($1, $2, $3, $4)",
             self.user_id,
             self.username,
             password_hash,
+            self.role,
         )
         .execute(pool)
         .await
         .expect("Failed to store test user.");
     }
+    pub async fn login(&self, app: &TestApp) -> reqwest::Response {
+        app.post_login(&serde_json::json!({
+            "username": &self.username,
+            "password": &self.password
+        }))
+        .await
+    }
 }
 
 impl TestApp {
+    /// Store another user besides `test_user`, the owner.
+    pub async fn create_user(&self, role: &str) -> TestUser {
+        let user = TestUser::generate_with_role(role);
+        user.store(&self.db_pool).await;
+        user
+    }
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_users_html(&self) -> String {
+        self.get_users().await.text().await.unwrap()
+    }
+    /// POST a form to `/admin/users`, or to one of its actions, e.g. `invite`.
+    pub async fn post_users<Body>(&self, action: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let url = match action {
+            "" => format!("{}/admin/users", &self.address),
+            action => format!("{}/admin/users/{}", &self.address, action),
+        };
+        self.api_client
+            .post(url)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.issue_delivery_settings,
+                &self.subscriber_links,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+    pub async fn release_due_issues(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_release_issue(&self.db_pool).await.unwrap() {
+                break;
+            }
+        }
+    }
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -70,7 +149,7 @@ impl TestApp {
     }
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -80,7 +159,185 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_change_email_html(&self) -> String {
+        self.get_change_email().await.text().await.unwrap()
+    }
+    pub async fn get_change_email(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/email", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/email", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_dead_letters_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/dead_letters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_replay_dead_letter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/dead_letters/replay", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_scheduled_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/scheduled_issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_reschedule_issue<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/scheduled_issues/reschedule",
+                &self.address
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_cancel_scheduled_issue<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/scheduled_issues/cancel", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_publish_newsletter(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_publish_newsletter_html(&self) -> String {
+        self.get_publish_newsletter().await.text().await.unwrap()
+    }
+    pub async fn post_publish_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_drafts<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/drafts/{}", &self.address, draft_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_draft_html(&self, draft_id: Uuid) -> String {
+        self.get_draft(draft_id).await.text().await.unwrap()
+    }
+    pub async fn post_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/drafts/{}", &self.address, draft_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_draft_preview(&self, draft_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/drafts/{}/preview",
+                &self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_send_test_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/drafts/{}/test", &self.address, draft_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_publish_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/drafts/{}/publish",
+                &self.address, draft_id
+            ))
             .form(body)
             .send()
             .await
@@ -89,7 +346,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -99,7 +356,7 @@ impl TestApp {
     }
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -112,7 +369,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             // This `reqwest` method makes sure that the body is URL-encoded
             // and the `Content-Type` header is set accordingly.
             .form(body)
@@ -123,7 +380,7 @@ impl TestApp {
 
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -146,14 +403,122 @@ impl TestApp {
             confirmation_link.set_port(Some(self.port)).unwrap();
             confirmation_link
         };
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
+    /// Extract the unsubscribe link from the `List-Unsubscribe` header of a
+    /// newsletter email.
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let header = body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .unwrap();
+        let raw_link = header["Value"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        let mut unsubscribe_link = reqwest::Url::parse(raw_link).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+    /// Extract the link to the preference centre from the footer of a
+    /// newsletter email.
+    pub fn get_preferences_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let raw_link = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .map(|l| l.as_str().to_owned())
+            .find(|l| l.contains("/subscriptions/preferences"))
+            .unwrap();
+        let mut preferences_link = reqwest::Url::parse(&raw_link).unwrap();
+        assert_eq!(preferences_link.host_str().unwrap(), "127.0.0.1");
+        preferences_link.set_port(Some(self.port)).unwrap();
+        preferences_link
+    }
+    /// Extract the link to the web version from the top of a newsletter email.
+    pub fn get_web_version_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let raw_link = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .map(|l| l.as_str().to_owned())
+            .find(|l| l.contains("/issues/"))
+            .unwrap();
+        let mut web_version_link = reqwest::Url::parse(&raw_link).unwrap();
+        assert_eq!(web_version_link.host_str().unwrap(), "127.0.0.1");
+        web_version_link.set_port(Some(self.port)).unwrap();
+        web_version_link
+    }
+    pub async fn get_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn get_issue(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/{}", &self.address, newsletter_issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_preferences_html(&self, preferences_link: &reqwest::Url) -> String {
+        self.api_client
+            .get(preferences_link.clone())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_preferences(
+        &self,
+        preferences_link: &reqwest::Url,
+        body: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.api_client
+            .post(preferences_link.clone())
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.post_newsletters_with_idempotency_key(body, &Uuid::new_v4().to_string())
+            .await
+    }
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/newsletter", &self.address))
+            .post(format!("{}/newsletter", &self.address))
             .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_email_webhook(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/email", &self.address))
+            .basic_auth(
+                &self.email_webhook_settings.username,
+                Some(self.email_webhook_settings.password.expose_secret()),
+            )
             .json(&body)
             .send()
             .await
@@ -161,6 +526,84 @@ impl TestApp {
     }
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    // We now inspect the requests received by the mock Postmark server
+    // to retrieve the confirmation link and return it
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+pub async fn create_list(app: &TestApp, slug: &str) -> Uuid {
+    let list_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, slug, name) VALUES ($1, $2, $2)",
+        list_id,
+        slug
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to create list.");
+    list_id
+}
+
+/// Subscribe `email` to the list with the given slug and click on the
+/// confirmation link.
+pub async fn create_confirmed_list_subscriber(app: &TestApp, email: &str, list: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create confirmed list subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(&[("name", "le guin"), ("email", email), ("list", list)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .error_for_status()
+        .unwrap();
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
 // Ensure that the `tracing` stack is only initialised once using `once_cell`
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
@@ -181,6 +624,11 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 // Launch our application in the background
 #[allow(unused)]
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, with the test settings adjusted by `configure` first.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // The first time `initialize` is invoked the code in `TRACING` is executed.
     // All other invocations will instead skip execution.
     Lazy::force(&TRACING);
@@ -195,8 +643,14 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.set_base_url(email_server.uri());
+        // Retry failed deliveries straight away
+        c.issue_delivery.base_backoff_milliseconds = 0;
+        // Only the tests about welcome emails expect them
+        c.welcome_email.enabled = false;
+        configure(&mut c);
         c
     };
+    let subscriber_links = configuration.application.subscriber_links();
     // Create and migrate the database
     configure_database(&configuration.database).await;
 
@@ -206,7 +660,7 @@ pub async fn spawn_app() -> TestApp {
     let port = app.port();
     let address = format!("http://127.0.0.1:{}", port);
 
-    let _ = tokio::spawn(app.run_until_stopped());
+    tokio::spawn(app.run_until_stopped());
 
     let db_pool = get_connection_pool(&configuration.database);
     let api_client = reqwest::Client::builder()
@@ -215,20 +669,33 @@ pub async fn spawn_app() -> TestApp {
         .build()
         .unwrap();
     // Launch a mock server to stand in for Postmark's API
-    let mut test_app = TestApp {
+    let test_app = TestApp {
         port,
         address,
         db_pool,
         email_server,
         test_user: TestUser::generate(),
         api_client,
+        email_client: configuration.email_client.clone().client(),
+        issue_delivery_settings: configuration.issue_delivery,
+        subscriber_links,
+        email_webhook_settings: configuration.email_client.webhook.clone(),
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
 }
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
-    // Create database
+    let connection_pool = create_database(config).await;
+    sqlx::migrate!("./migrations")
+        .run(&connection_pool)
+        .await
+        .expect("Failed to migrate the database");
+    connection_pool
+}
+
+/// Create an empty database, leaving migrations to the caller.
+pub async fn create_database(config: &DatabaseSettings) -> PgPool {
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
         .expect("Failed to connect to Postgres");
@@ -236,15 +703,9 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
         .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
         .await
         .expect("Failed to create database.");
-    // Migrate database
-    let connection_pool = PgPool::connect_with(config.with_db())
+    PgPool::connect_with(config.with_db())
         .await
-        .expect("Failed to connect to Postgres.");
-    sqlx::migrate!("./migrations")
-        .run(&connection_pool)
-        .await
-        .expect("Failed to migrate the database");
-    connection_pool
+        .expect("Failed to connect to Postgres.")
 }
 
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
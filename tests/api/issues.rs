@@ -0,0 +1,118 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use chrono::Utc;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp, body: serde_json::Value) -> Uuid {
+    app.post_newsletters(body).await.error_for_status().unwrap();
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } })
+}
+
+#[tokio::test]
+async fn published_issues_are_archived_with_their_author() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let issue_id = publish_newsletter(&app, newsletter_request_body()).await;
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT title, text_content, html_content, author_user_id, published_at FROM newsletter_issues"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.title, "Newsletter title");
+    assert_eq!(saved.text_content, "Newsletter body as plain text");
+    assert_eq!(saved.html_content, "<p>Newsletter body as HTML</p>");
+    assert_eq!(saved.author_user_id, Some(app.test_user.user_id));
+    assert!(saved.published_at.is_some());
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/issues/{}">Newsletter title</a>"#,
+        issue_id
+    )));
+}
+
+#[tokio::test]
+async fn the_web_version_shows_the_issue_to_anyone() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_newsletter(&app, newsletter_request_body()).await;
+    // Act
+    let response = app.get_issue(issue_id).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Newsletter title</h1>"));
+    assert!(html_page.contains("<p>Newsletter body as HTML</p>"));
+    assert!(html_page.contains(&format!("by {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn the_web_version_fills_in_the_placeholders() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut body = newsletter_request_body();
+    body["content"]["html"] =
+        serde_json::json!(r#"<p>Hi {{name}}!</p><a href="{{unsubscribe_url}}">Leave</a>"#);
+    let issue_id = publish_newsletter(&app, body).await;
+    // Act
+    let html_page = app.get_issue(issue_id).await.text().await.unwrap();
+    // Assert
+    assert!(!html_page.contains("{{"));
+    assert!(html_page.contains("<p>Hi reader!</p>"));
+}
+
+#[tokio::test]
+async fn scheduled_and_unknown_issues_are_not_public() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut body = newsletter_request_body();
+    body["send_at"] = serde_json::json!(Utc::now() + Duration::from_secs(60 * 60));
+    let issue_id = publish_newsletter(&app, body).await;
+    // Act
+    let scheduled_response = app.get_issue(issue_id).await;
+    let unknown_response = app.get_issue(Uuid::new_v4()).await;
+    // Assert
+    assert_eq!(scheduled_response.status().as_u16(), 404);
+    assert_eq!(unknown_response.status().as_u16(), 404);
+    assert!(!app.get_issues_html().await.contains("Newsletter title"));
+}
+
+#[tokio::test]
+async fn newsletter_emails_link_to_their_web_version() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_newsletter(&app, newsletter_request_body()).await;
+    // Act
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let web_version_link = app.get_web_version_link(&email_request);
+    assert_eq!(web_version_link.path(), format!("/issues/{}", issue_id));
+    let response = reqwest::get(web_version_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
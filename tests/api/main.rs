@@ -1,8 +1,20 @@
 mod admin_dashboard;
+mod admin_newsletters;
+mod admin_users;
+mod change_email;
+mod change_password;
+mod dead_letters;
+mod drafts;
 mod health_check;
 mod helpers;
+mod issues;
 mod login;
+mod migrations;
 mod newsletter;
+mod scheduled_issues;
 mod subscriptions;
 mod subscriptions_confirm;
-mod change_password;
\ No newline at end of file
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
+mod tracking;
+mod webhooks;
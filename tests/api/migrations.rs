@@ -0,0 +1,95 @@
+use crate::helpers::create_database;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+use uuid::Uuid;
+use zero2prod::configuration::get_configuration;
+
+/// The migration introducing mailing lists.
+const LISTS_MIGRATION: i64 = 20240320143012;
+
+/// A database with every migration before `version` applied.
+async fn database_before(version: i64) -> PgPool {
+    let mut configuration = get_configuration().expect("Failed to read configuration");
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    let connection_pool = create_database(&configuration.database).await;
+    let mut migrator: Migrator = sqlx::migrate!("./migrations");
+    migrator.migrations = migrator
+        .migrations
+        .iter()
+        .filter(|m| m.version < version)
+        .cloned()
+        .collect::<Vec<_>>()
+        .into();
+    migrator
+        .run(&connection_pool)
+        .await
+        .expect("Failed to migrate the database");
+    connection_pool
+}
+
+async fn insert_subscriber(pool: &PgPool, email: &str, status: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+        VALUES ($1, $2, 'le guin', now(), $3)",
+    )
+    .bind(subscriber_id)
+    .bind(email)
+    .bind(status)
+    .execute(pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+/// The status of the address of a subscriber and of their newsletter
+/// subscription.
+async fn statuses(pool: &PgPool, subscriber_id: Uuid) -> (String, String) {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.status, l.status AS list_status
+        FROM subscriptions s
+        JOIN list_subscriptions l ON l.subscriber_id = s.id
+        WHERE s.id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    (row.status, row.list_status)
+}
+
+#[tokio::test]
+async fn unsubscribing_before_confirming_an_address_keeps_it_unconfirmed() {
+    // Arrange
+    let pool = database_before(LISTS_MIGRATION).await;
+    // Unsubscribed with the confirmation of their new address outstanding
+    let pending = insert_subscriber(&pool, "ursula@example.com", "unsubscribed").await;
+    sqlx::query(
+        "INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ('token', $1)",
+    )
+    .bind(pending)
+    .execute(&pool)
+    .await
+    .unwrap();
+    // Unsubscribed after confirming, which burnt their tokens
+    let confirmed = insert_subscriber(&pool, "ursula_le_guin@gmail.com", "unsubscribed").await;
+    // Act
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    // Assert
+    assert_eq!(
+        statuses(&pool, pending).await,
+        ("pending_confirmation".into(), "unsubscribed".into())
+    );
+    assert_eq!(
+        statuses(&pool, confirmed).await,
+        ("confirmed".into(), "unsubscribed".into())
+    );
+    let token = sqlx::query!("SELECT list_id FROM subscription_tokens")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    // Confirming the token validates the address without rejoining the list
+    assert_eq!(token.list_id, None);
+}
@@ -1,40 +1,14 @@
-use crate::helpers::{spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    create_confirmed_list_subscriber, create_confirmed_subscriber, create_list,
+    create_unconfirmed_subscriber, spawn_app, spawn_app_with,
+};
+use sqlx::Executor;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
-
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-    // We now inspect the requests received by the mock Postmark server
-    // to retrieve the confirmation link and return it
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(&email_request)
-}
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
+use zero2prod::issue_delivery_worker::try_execute_task;
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -52,6 +26,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let response = app.post_newsletters(newsletter_request_body).await;
     // Assert
     assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we haven't sent the newsletter email
 }
 
@@ -71,6 +46,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let response = app.post_newsletters(newsletter_request_body).await;
     // Assert
     assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we have sent the newsletter email
 }
 #[tokio::test]
@@ -104,7 +80,7 @@ async fn requests_missing_authorization_are_rejected() {
     // Arrange
     let app = spawn_app().await;
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletter", &app.address))
+        .post(format!("{}/newsletter", &app.address))
         .json(&serde_json::json!({"title": "Newsletter title", "content": {"text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>"}}))
         .send()
         .await
@@ -124,7 +100,7 @@ async fn non_existing_user_is_rejected() {
     let username = Uuid::new_v4().to_string();
     let password = Uuid::new_v4().to_string();
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletter", &app.address))
+        .post(format!("{}/newsletter", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>"}}))
         .send()
@@ -147,7 +123,7 @@ async fn invalid_password_is_rejected() {
     let password = Uuid::new_v4().to_string();
     assert_ne!(app.test_user.password, password);
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletter", &app.address))
+        .post(format!("{}/newsletter", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({ "title": "Newsletter title", "content": {"text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>"}}))
         .send()
@@ -160,3 +136,543 @@ async fn invalid_password_is_rejected() {
         response.headers()["WWW-Authenticate"]
     );
 }
+
+#[tokio::test]
+async fn requests_missing_an_idempotency_key_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletter", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "title": "Newsletter title", "content": {"text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>"}}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } });
+    let idempotency_key = Uuid::new_v4().to_string();
+    // Act - Part 1 - Publish the newsletter
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    // Act - Part 2 - Retry the request with the same key
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body, &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn concurrent_newsletter_submission_is_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        // Setting a long delay to ensure that the second request
+        // arrives before the first one completes
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } });
+    let idempotency_key = Uuid::new_v4().to_string();
+    // Act - Submit two newsletter requests concurrently
+    let response1 = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key);
+    let response2 =
+        app.post_newsletters_with_idempotency_key(newsletter_request_body, &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+    // Assert
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn publishing_enqueues_one_delivery_task_per_confirmed_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        // The request handler must not talk to Postmark directly
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery tasks.");
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_once_to_subscribers_of_several_target_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "poetry").await;
+    create_list(&app, "fiction").await;
+    create_confirmed_list_subscriber(&app, "ursula_le_guin@gmail.com", "poetry").await;
+    create_confirmed_list_subscriber(&app, "ursula_le_guin@gmail.com", "fiction").await;
+    create_confirmed_list_subscriber(&app, "octavia_butler@gmail.com", "fiction").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(batch_response(&[0, 0])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", }, "lists": ["poetry", "fiction"] });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    let batch = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&batch.body).unwrap();
+    let mut recipients: Vec<_> = batch.iter().map(|email| email["To"].as_str()).collect();
+    recipients.sort();
+    assert_eq!(
+        recipients,
+        [
+            Some("octavia_butler@gmail.com"),
+            Some("ursula_le_guin@gmail.com")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn recipients_rejected_from_a_batch_are_dead_lettered_individually() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "poetry").await;
+    create_confirmed_list_subscriber(&app, "ursula_le_guin@gmail.com", "poetry").await;
+    create_confirmed_list_subscriber(&app, "octavia_butler@gmail.com", "poetry").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &wiremock::Request| {
+            let batch: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let codes: Vec<_> = batch
+                .iter()
+                .map(|email| match email["To"].as_str() {
+                    Some("octavia_butler@gmail.com") => 406,
+                    _ => 0,
+                })
+                .collect();
+            ResponseTemplate::new(200).set_body_json(batch_response(&codes))
+        })
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", }, "lists": ["poetry"] });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let dead_letters =
+        sqlx::query!("SELECT subscriber_email, error_code FROM issue_delivery_dead_letters")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0].subscriber_email, "octavia_butler@gmail.com");
+    assert_eq!(dead_letters[0].error_code, Some(406));
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn failing_to_record_one_delivery_does_not_resend_the_batch() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "poetry").await;
+    create_confirmed_list_subscriber(&app, "ursula_le_guin@gmail.com", "poetry").await;
+    create_confirmed_list_subscriber(&app, "octavia_butler@gmail.com", "poetry").await;
+    app.db_pool
+        .execute(
+            r#"
+            CREATE FUNCTION reject_octavia() RETURNS trigger AS $$
+            BEGIN
+                IF NEW.subscriber_email = 'octavia_butler@gmail.com' THEN
+                    RAISE EXCEPTION 'Simulated database failure';
+                END IF;
+                RETURN NEW;
+            END;
+            $$ LANGUAGE plpgsql;
+            CREATE TRIGGER reject_octavia BEFORE INSERT ON issue_deliveries
+            FOR EACH ROW EXECUTE FUNCTION reject_octavia();
+            "#,
+        )
+        .await
+        .unwrap();
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(batch_response(&[0, 0])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", }, "lists": ["poetry"] });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let delivered = sqlx::query!("SELECT subscriber_email FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].subscriber_email, "ursula_le_guin@gmail.com");
+    // The other task waits for its claim to expire instead of being sent
+    // again straight away
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, "octavia_butler@gmail.com");
+}
+
+#[tokio::test]
+async fn concurrent_workers_keep_several_calls_in_flight() {
+    // Arrange
+    let app = spawn_app_with(|c| c.issue_delivery.batch_size = 1).await;
+    create_list(&app, "poetry").await;
+    create_confirmed_list_subscriber(&app, "ursula_le_guin@gmail.com", "poetry").await;
+    create_confirmed_list_subscriber(&app, "octavia_butler@gmail.com", "poetry").await;
+    let delay = Duration::from_millis(500);
+    let arrivals = Arc::new(Mutex::new(Vec::new()));
+    let arrivals_ = arrivals.clone();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(move |_: &wiremock::Request| {
+            arrivals_.lock().unwrap().push(Instant::now());
+            ResponseTemplate::new(200).set_delay(delay)
+        })
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", }, "lists": ["poetry"] });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // Act
+    let execute = || {
+        try_execute_task(
+            &app.db_pool,
+            &app.email_client,
+            &app.issue_delivery_settings,
+            &app.subscriber_links,
+        )
+    };
+    let (first, second) = tokio::join!(execute(), execute());
+    // Assert
+    first.unwrap();
+    second.unwrap();
+    let arrivals = arrivals.lock().unwrap();
+    assert_eq!(arrivals.len(), 2);
+    // The second email reached the provider before the first was answered
+    assert!(arrivals[1] - arrivals[0] < delay);
+}
+
+#[tokio::test]
+async fn the_provider_message_id_is_kept_for_each_delivery() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "ursula_le_guin@gmail.com",
+            "SubmittedAt": "2024-03-29T07:25:01.4178645-04:00",
+            "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+            "ErrorCode": 0,
+            "Message": "OK"
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let delivery =
+        sqlx::query!("SELECT subscriber_email, message_id, submitted_at FROM issue_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(delivery.subscriber_email, "ursula_le_guin@gmail.com");
+    assert_eq!(
+        delivery.message_id.as_deref(),
+        Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+    );
+    assert_eq!(
+        delivery.submitted_at.to_rfc3339(),
+        "2024-03-29T11:25:01.417864+00:00"
+    );
+}
+
+/// Postmark's answer to a batch: one entry per email, 0 meaning accepted.
+fn batch_response(error_codes: &[i64]) -> serde_json::Value {
+    error_codes
+        .iter()
+        .map(|code| serde_json::json!({ "ErrorCode": code, "Message": "OK" }))
+        .collect()
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_subscribers_of_other_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "poetry").await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", }, "lists": ["poetry"] });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", }, "lists": ["newsletter", "poetry"] });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn newsletters_are_personalised_for_each_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Dear {{name}} <{{email}}>, leave at {{unsubscribe_url}}", "html": "<p>Dear {{ name }}</p>", } });
+    app.post_newsletters(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let unsubscribe_link = app.get_unsubscribe_link(&email_request);
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("Dear le guin <ursula_le_guin@gmail.com>, leave at http://127.0.0.1/subscriptions/unsubscribe?token="));
+    assert!(text_body.contains(unsubscribe_link.query().unwrap()));
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(&format!(
+        "<p>Dear {}</p>",
+        htmlescape::encode_attribute("le guin")
+    )));
+}
+
+#[tokio::test]
+async fn newsletters_with_unknown_template_variables_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        serde_json::json!({ "title": "Newsletter title", "content": { "text": "Dear {{nmae}}", "html": "<p>Dear {{name}}</p>", } }),
+        serde_json::json!({ "title": "Newsletter title", "content": { "text": "Dear {{name}}", "html": "<p>Dear {{name</p>", } }),
+    ];
+    for invalid_body in test_cases {
+        // Act
+        let response = app.post_newsletters(invalid_body).await;
+        // Assert
+        assert_eq!(response.status().as_u16(), 400);
+    }
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn subscribers_receive_the_translation_closest_to_their_locale() {
+    // Arrange
+    let app = spawn_app().await;
+    for body in [
+        "name=anne&email=anne%40example.com&locale=fr-CA",
+        "name=bernd&email=bernd%40example.com&locale=de",
+    ] {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&app.email_server)
+            .await;
+        app.post_subscriptions(body.into()).await;
+        let email_request = app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let confirmation_links = app.get_confirmation_links(&email_request);
+        reqwest::get(confirmation_links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(batch_response(&[0, 0])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": { "text": "Hello {{name}}", "html": "<p>Hello {{name}}</p>" },
+        "translations": [
+            { "locale": "fr", "title": "Titre", "content": { "text": "Bonjour {{name}}", "html": "<p>Bonjour {{name}}</p>" } },
+        ],
+    });
+    app.post_newsletters(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&email_request.body).unwrap();
+    let mut subjects: Vec<_> = batch
+        .iter()
+        .map(|email| {
+            (
+                email["To"].as_str().unwrap().to_owned(),
+                email["Subject"].as_str().unwrap().to_owned(),
+                email["TextBody"].as_str().unwrap().to_owned(),
+            )
+        })
+        .collect();
+    subjects.sort();
+    assert_eq!(subjects[0].0, "anne@example.com");
+    assert_eq!(subjects[0].1, "Titre");
+    assert!(subjects[0].2.contains("Bonjour anne"));
+    assert_eq!(subjects[1].0, "bernd@example.com");
+    assert_eq!(subjects[1].1, "Newsletter title");
+    assert!(subjects[1].2.contains("Hello bernd"));
+}
+
+#[tokio::test]
+async fn newsletters_with_invalid_translations_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let translation = |locale: &str| serde_json::json!({ "locale": locale, "title": "Titre", "content": { "text": "Bonjour", "html": "<p>Bonjour</p>" } });
+    let test_cases = vec![
+        vec![translation("not a locale")],
+        vec![translation("fr"), translation("fr")],
+    ];
+    for translations in test_cases {
+        // Act
+        let response = app
+            .post_newsletters(serde_json::json!({
+                "title": "Newsletter title",
+                "content": { "text": "Hello", "html": "<p>Hello</p>" },
+                "translations": translations,
+            }))
+            .await;
+        // Assert
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = app.create_user("viewer").await;
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/newsletter", &app.address))
+        .basic_auth(&viewer.username, Some(&viewer.password))
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({ "title": "Newsletter title", "content": { "text": "Body", "html": "<p>Body</p>" } }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -0,0 +1,226 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn schedule_newsletter(app: &TestApp, send_at: DateTime<Utc>) -> Uuid {
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", }, "send_at": send_at });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    sqlx::query!("SELECT newsletter_issue_id FROM scheduled_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_their_send_at() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    schedule_newsletter(&app, Utc::now() + Duration::from_secs(60 * 60)).await;
+    app.release_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let issue = sqlx::query!("SELECT published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.published_at.is_none());
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    schedule_newsletter(&app, Utc::now() - Duration::from_secs(1)).await;
+    app.release_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let issue = sqlx::query!("SELECT published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.published_at.is_some());
+    let n_scheduled = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM scheduled_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_scheduled, 0);
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_scheduled_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/admin/scheduled_issues", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_listed_and_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = schedule_newsletter(&app, Utc::now() + Duration::from_secs(60 * 60)).await;
+    login(&app).await;
+    // Act - Part 1 - List the scheduled issues
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains(&issue_id.to_string()));
+    // Act - Part 2 - Move the issue into the past
+    let send_at = (Utc::now() - Duration::from_secs(1)).to_rfc3339();
+    let response = app
+        .post_reschedule_issue(&serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "send_at": send_at,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/scheduled_issues");
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The issue has been rescheduled.</i></p>"));
+    // Act - Part 3 - Release it
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.release_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn rescheduling_rejects_invalid_timestamps() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = schedule_newsletter(&app, Utc::now() + Duration::from_secs(60 * 60)).await;
+    login(&app).await;
+    // Act
+    let response = app
+        .post_reschedule_issue(&serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "send_at": "<b>tomorrow</b>",
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/scheduled_issues");
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page
+        .contains("<p><i>&lt;b&gt;tomorrow&lt;/b&gt; is not a valid RFC 3339 timestamp.</i></p>"));
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_never_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = schedule_newsletter(&app, Utc::now() + Duration::from_secs(1)).await;
+    login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act - Part 1 - Cancel the issue
+    let response = app
+        .post_cancel_scheduled_issue(&serde_json::json!({
+            "newsletter_issue_id": issue_id,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/scheduled_issues");
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The issue has been cancelled.</i></p>"));
+    assert!(!html_page.contains(&issue_id.to_string()));
+    // Act - Part 2 - Cancelling twice is reported
+    app.post_cancel_scheduled_issue(&serde_json::json!({
+        "newsletter_issue_id": issue_id,
+    }))
+    .await;
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The issue has already been sent or cancelled.</i></p>"));
+    // Act - Part 3 - Nothing is left to release
+    sqlx::query!("UPDATE scheduled_issues SET send_at = now() - interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.release_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn translated_issues_can_be_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    let send_at = Utc::now() + Duration::from_secs(60 * 60);
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>" },
+        "translations": [
+            { "locale": "fr", "title": "Titre", "content": { "text": "Bonjour", "html": "<p>Bonjour</p>" } },
+        ],
+        "send_at": send_at,
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM scheduled_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    login(&app).await;
+    // Act
+    let response = app
+        .post_cancel_scheduled_issue(&serde_json::json!({
+            "newsletter_issue_id": issue_id,
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/scheduled_issues");
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The issue has been cancelled.</i></p>"));
+    let n_translations =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issue_translations"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_translations, 0);
+}
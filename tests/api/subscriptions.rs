@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{create_confirmed_subscriber, create_list, spawn_app};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -105,6 +105,26 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
+#[tokio::test]
+async fn the_confirmation_email_has_an_html_and_a_plain_text_body() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await;
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"].as_str().unwrap().contains("<a href="));
+    assert!(!body["TextBody"].as_str().unwrap().contains('<'));
+}
+
 #[tokio::test]
 async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Arrange
@@ -120,3 +140,201 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribing_twice_before_confirming_sends_a_fresh_confirmation_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_requests = &app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+    let saved = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn subscribing_with_an_already_confirmed_email_succeeds_without_sending_an_email() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_without_a_list_joins_the_default_newsletter() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await;
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT l.slug, s.status FROM list_subscriptions s JOIN lists l USING (list_id)"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved list subscription.");
+    assert_eq!(saved.slug, "newsletter");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_unknown_or_invalid_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&list=poetry",
+            "an unknown list",
+        ),
+        (
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&list=Poetry%20Weekly",
+            "an invalid slug",
+        ),
+    ];
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_subscriptions(body.into()).await;
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn each_list_subscription_is_confirmed_separately() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "poetry").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&list=poetry".into())
+        .await;
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let poetry_confirmation_links = app.get_confirmation_links(&email_requests[1]);
+    // Act
+    reqwest::get(poetry_confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT l.slug, s.status FROM list_subscriptions s JOIN lists l USING (list_id) ORDER BY l.slug"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved list subscriptions.");
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].slug, "newsletter");
+    assert_eq!(saved[0].status, "pending_confirmation");
+    assert_eq!(saved[1].slug, "poetry");
+    assert_eq!(saved[1].status, "confirmed");
+}
+
+#[tokio::test]
+async fn the_confirmation_email_is_written_in_the_language_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr_CA";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await;
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"].as_str().unwrap(), "Bienvenue !");
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("pour confirmer votre inscription"));
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "fr-CA");
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_an_invalid_locale() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=not%20a%20locale";
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_home_page_follows_the_accept_language_header() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/", &app.address))
+        .header("Accept-Language", "de;q=0.5, fr-CH, en;q=0.8")
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.headers()["Content-Language"], "fr");
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Bienvenue dans notre newsletter !"));
+}
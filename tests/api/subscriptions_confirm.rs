@@ -1,4 +1,7 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{
+    create_confirmed_list_subscriber, create_list, create_unconfirmed_subscriber, spawn_app,
+    spawn_app_with, TestApp,
+};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -26,7 +29,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
         .await;
     app.post_subscriptions(body.into()).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
     let response = reqwest::get(confirmation_links.html).await.unwrap();
     assert_eq!(response.status().as_u16(), 200);
 }
@@ -43,7 +46,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .await;
     app.post_subscriptions(body.into()).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
     // Act
     reqwest::get(confirmation_links.html)
         .await
@@ -59,3 +62,234 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '1 year'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn a_confirmation_link_superseded_by_a_new_subscription_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.post_subscriptions(body.into()).await;
+    let email_requests = &app.email_server.received_requests().await.unwrap();
+    let stale_link = app.get_confirmation_links(&email_requests[0]).html;
+    let fresh_link = app.get_confirmation_links(&email_requests[1]).html;
+    // Act
+    let stale_response = reqwest::get(stale_link).await.unwrap();
+    let fresh_response = reqwest::get(fresh_link).await.unwrap();
+    // Assert
+    assert_eq!(stale_response.status().as_u16(), 401);
+    assert_eq!(fresh_response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn confirmation_links_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn confirming_shows_a_page_in_the_language_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(response.headers()["Content-Language"], "fr");
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Votre inscription est confirmée."));
+}
+
+#[tokio::test]
+async fn invalid_and_expired_links_explain_what_went_wrong() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '1 year'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let expired = reqwest::get(confirmation_links.html).await.unwrap();
+    let invalid = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=invalid",
+            app.address
+        ))
+        .header("Accept-Language", "de")
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(expired.status().as_u16(), 410);
+    assert!(expired
+        .text()
+        .await
+        .unwrap()
+        .contains("This confirmation link has expired."));
+    assert_eq!(invalid.status().as_u16(), 401);
+    assert_eq!(invalid.headers()["Content-Language"], "de");
+    assert!(invalid
+        .text()
+        .await
+        .unwrap()
+        .contains("Dieser Bestätigungslink ist ungültig."));
+}
+
+async fn spawn_app_with_welcome_emails() -> TestApp {
+    spawn_app_with(|c| c.welcome_email.enabled = true).await
+}
+
+#[tokio::test]
+async fn confirming_sends_a_welcome_email() {
+    // Arrange
+    let app = spawn_app_with_welcome_emails().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["Subject"], "You are in!");
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("Hi le guin,"));
+    assert!(text_body.contains("http://127.0.0.1/issues"));
+}
+
+#[tokio::test]
+async fn the_welcome_email_is_written_in_the_language_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app_with_welcome_emails().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Vous êtes inscrit !");
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("Bonjour le guin,"));
+    assert!(text_body.contains("http://127.0.0.1/issues"));
+}
+
+#[tokio::test]
+async fn joining_another_list_does_not_send_a_second_welcome_email() {
+    // Arrange
+    let app = spawn_app_with_welcome_emails().await;
+    create_list(&app, "poetry").await;
+    let mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Confirmation and welcome emails")
+        .expect(2)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    drop(mock_guard);
+    // Act - Assert
+    // The helper expects a single email: the confirmation one
+    create_confirmed_list_subscriber(&app, "ursula_le_guin@gmail.com", "poetry").await;
+}
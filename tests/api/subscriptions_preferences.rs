@@ -0,0 +1,236 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_list, spawn_app, TestApp,
+};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Publish an issue to the confirmed subscriber and return the preferences
+/// link found in the email we sent them.
+async fn preferences_link_from_newsletter(app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } });
+    app.post_newsletters(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_preferences_link(&email_request)
+}
+
+fn redirect_target(link: &reqwest::Url) -> String {
+    format!("{}?{}", link.path(), link.query().unwrap())
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "poetry").await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_link = preferences_link_from_newsletter(&app).await;
+    // Act
+    let html_page = app.get_preferences_html(&preferences_link).await;
+    // Assert
+    let attribute = |s: &str| format!(r#"value="{}""#, htmlescape::encode_attribute(s));
+    assert!(html_page.contains(&attribute("le guin")));
+    assert!(html_page.contains(&attribute("ursula_le_guin@gmail.com")));
+    assert!(html_page.contains(r#"value="5b1f0a8e-6f4c-4c1e-9a57-3f0d2c1e7b42" checked>"#));
+    assert!(html_page.contains("poetry"));
+}
+
+#[tokio::test]
+async fn tampered_preferences_links_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let mut preferences_link = preferences_link_from_newsletter(&app).await;
+    preferences_link.set_query(Some("token=bm90LWEtdXVpZA.c2lnbmF0dXJl"));
+    // Act
+    let get_response = app
+        .api_client
+        .get(preferences_link.clone())
+        .send()
+        .await
+        .unwrap();
+    let post_response = app
+        .post_preferences(
+            &preferences_link,
+            &[("name", "Ursula"), ("email", "ursula_le_guin@gmail.com")],
+        )
+        .await;
+    // Assert
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_and_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let poetry = create_list(&app, "poetry").await;
+    create_list(&app, "fiction").await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_link = preferences_link_from_newsletter(&app).await;
+    // Act - Part 1 - Submit the form, leaving the newsletter for poetry
+    let response = app
+        .post_preferences(
+            &preferences_link,
+            &[
+                ("name", "Ursula K. Le Guin"),
+                ("email", "ursula_le_guin@gmail.com"),
+                ("lists", &poetry.to_string()),
+            ],
+        )
+        .await;
+    assert_is_redirect_to(&response, &redirect_target(&preferences_link));
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_preferences_html(&preferences_link).await;
+    assert!(html_page.contains("<p><i>Your preferences have been updated.</i></p>"));
+    // Assert
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.status, "confirmed");
+    let lists: Vec<(String, String)> = sqlx::query!(
+        r#"
+        SELECT l.slug, s.status
+        FROM list_subscriptions s
+        JOIN lists l USING (list_id)
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect();
+    assert_eq!(
+        lists,
+        vec![
+            ("newsletter".to_owned(), "unsubscribed".to_owned()),
+            ("poetry".to_owned(), "confirmed".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn issues_follow_the_lists_picked_in_the_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_link = preferences_link_from_newsletter(&app).await;
+    app.post_preferences(
+        &preferences_link,
+        &[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")],
+    )
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } });
+    app.post_newsletters(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_link = preferences_link_from_newsletter(&app).await;
+    let test_cases = vec![
+        (
+            [("name", ""), ("email", "ursula_le_guin@gmail.com")],
+            "<p><i> is not a valid subscriber name.</i></p>",
+        ),
+        (
+            [("name", "Ursula"), ("email", "definitely-not-an-email")],
+            "<p><i>definitely-not-an-email is not a valid subscriber email.</i></p>",
+        ),
+    ];
+    for (body, error_message) in test_cases {
+        // Act
+        let response = app.post_preferences(&preferences_link, &body).await;
+        assert_is_redirect_to(&response, &redirect_target(&preferences_link));
+        // Assert
+        let html_page = app.get_preferences_html(&preferences_link).await;
+        assert!(html_page.contains(error_message));
+    }
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn changing_the_email_address_requires_a_new_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_link = preferences_link_from_newsletter(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app
+        .post_preferences(
+            &preferences_link,
+            &[("name", "le guin"), ("email", "ursula@example.com")],
+        )
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, &redirect_target(&preferences_link));
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@example.com");
+    assert_eq!(saved.status, "pending_confirmation");
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
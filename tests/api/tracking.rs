@@ -0,0 +1,130 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Publish an issue linking to an external page and deliver it, returning
+/// the HTML body of the email.
+async fn deliver_issue(app: &TestApp, tracking: bool) -> String {
+    create_confirmed_subscriber(app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Read https://example.com/article",
+            "html": r#"<p>Read <a href="https://example.com/article?a=1&amp;b=2">this</a></p>"#,
+        },
+        "tracking": tracking
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    body["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+/// Find the link to the tracking route `prefix` in the email and point it at
+/// the test server.
+fn tracking_link(app: &TestApp, html: &str, prefix: &str) -> reqwest::Url {
+    let raw_link = linkify::LinkFinder::new()
+        .links(html)
+        .map(|l| l.as_str().to_owned())
+        .find(|l| l.contains(prefix))
+        .unwrap();
+    let mut link = reqwest::Url::parse(&raw_link).unwrap();
+    assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+async fn tracking_events(app: &TestApp) -> Vec<(String, Option<String>)> {
+    sqlx::query!("SELECT kind, url FROM issue_tracking_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.kind, r.url))
+        .collect()
+}
+
+#[tokio::test]
+async fn opens_and_clicks_of_tracked_issues_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = deliver_issue(&app, true).await;
+    assert!(!html.contains(r#"href="https://example.com"#));
+    let open_pixel = tracking_link(&app, &html, "/t/o/");
+    let click_link = tracking_link(&app, &html, "/t/c/");
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    // Act - Part 1 - Open the email
+    let response = client.get(open_pixel).send().await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    // Act - Part 2 - Click the link
+    let response = client.get(click_link).send().await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/article?a=1&b=2"
+    );
+    assert_eq!(
+        tracking_events(&app).await,
+        vec![
+            ("open".to_string(), None),
+            (
+                "click".to_string(),
+                Some("https://example.com/article?a=1&b=2".to_string())
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn issues_without_tracking_are_sent_untouched() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let html = deliver_issue(&app, false).await;
+    // Assert
+    assert!(html.contains(r#"<a href="https://example.com/article?a=1&amp;b=2">this</a>"#));
+    assert!(!html.contains("/t/o/"));
+    assert!(!html.contains("/t/c/"));
+}
+
+#[tokio::test]
+async fn tampered_tracking_links_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = deliver_issue(&app, true).await;
+    let mut click_link = tracking_link(&app, &html, "/t/c/");
+    // Point the signed link somewhere else
+    let tampered_path = click_link.path().replace("/t/c/", "/t/c/x");
+    click_link.set_path(&tampered_path);
+    // Act
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(click_link)
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(tracking_events(&app).await.is_empty());
+}
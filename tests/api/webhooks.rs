@@ -0,0 +1,190 @@
+use crate::helpers::{
+    create_confirmed_subscriber, create_list, create_unconfirmed_subscriber, spawn_app,
+    spawn_app_with,
+};
+use secrecy::ExposeSecret;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber_status(app: &crate::helpers::TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn events_without_valid_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let body =
+        serde_json::json!({ "RecordType": "SpamComplaint", "Email": "ursula_le_guin@gmail.com" });
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/webhooks/email", &app.address))
+        .basic_auth("postmark", Some("not-the-password"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        r#"Basic realm="webhooks""#,
+        response.headers()["WWW-Authenticate"]
+    );
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn hard_bounces_stop_deliveries_to_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let body = serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "TypeCode": 1,
+        "Email": "ursula_le_guin@gmail.com",
+    });
+    // Act
+    let response = app.post_email_webhook(body).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({ "title": "Newsletter title", "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>", } });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn spam_complaints_mark_the_subscriber_as_complained() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let body =
+        serde_json::json!({ "RecordType": "SpamComplaint", "Email": "ursula_le_guin@gmail.com" });
+    // Act
+    let response = app.post_email_webhook(body).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "complained");
+}
+
+fn hard_bounce() -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "TypeCode": 1,
+        "Email": "ursula_le_guin@gmail.com",
+    })
+}
+
+#[tokio::test]
+async fn bounced_addresses_get_no_confirmation_email_when_subscribing_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "poetry").await;
+    create_confirmed_subscriber(&app).await;
+    app.post_email_webhook(hard_bounce()).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&list=poetry".into())
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+    // Mock verifies on Drop that we haven't sent a confirmation email
+}
+
+#[tokio::test]
+async fn pending_confirmation_links_do_not_undo_a_bounce() {
+    // Arrange
+    let app = spawn_app_with(|c| c.welcome_email.enabled = true).await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    app.post_email_webhook(hard_bounce()).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Assert
+    assert_eq!(subscriber_status(&app).await, "bounced");
+    // Mock verifies on Drop that we haven't sent a welcome email
+}
+
+#[tokio::test]
+async fn soft_bounces_and_other_events_are_ignored() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    for body in [
+        serde_json::json!({ "RecordType": "Bounce", "Type": "SoftBounce", "Email": "ursula_le_guin@gmail.com" }),
+        serde_json::json!({ "RecordType": "Delivery", "Recipient": "ursula_le_guin@gmail.com" }),
+    ] {
+        // Act
+        let response = app.post_email_webhook(body).await;
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn malformed_events_are_only_reported_to_authenticated_callers() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = format!("{}/webhooks/email", &app.address);
+    let body = r#"{ "RecordType": "Bounce" "#;
+    // Act
+    let anonymous_response = app
+        .api_client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let authenticated_response = app
+        .api_client
+        .post(&url)
+        .basic_auth(
+            &app.email_webhook_settings.username,
+            Some(app.email_webhook_settings.password.expose_secret()),
+        )
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(anonymous_response.status().as_u16(), 401);
+    assert_eq!(authenticated_response.status().as_u16(), 400);
+}